@@ -0,0 +1,17 @@
+use ui::Grid;
+use ui::app::App;
+use ui::term::Terminal;
+use ui::util::{Updater, TextScroller};
+
+fn main() {
+    let mut grid = Grid::new_typed(Updater::new(TextScroller::new("a\nbb\nc\ndd\ng\nh\ni", 3, 5)), 0, 0,
+                                   Updater::new(TextScroller::new("abcdefg\nhijk\nlmnop\nqrstuv\nwxyz", 8, 3)), 11, 1);
+    let left = grid.top_left();
+    let right = grid.bottom_right();
+    grid.connect_left_right(left, right).unwrap();
+
+    let mut term = Terminal::new().unwrap();
+    term.enable_mouse().unwrap();
+    term.enable_paste().unwrap();
+    App::new(grid, term).unwrap().run().unwrap();
+}
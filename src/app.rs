@@ -0,0 +1,324 @@
+use crate::{Element, Message, Response};
+use crate::canvas::Canvas;
+use crate::input::Parser;
+use crate::keymap::{Action, Keymap};
+use crate::render::Renderer;
+
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+pub trait Backend: Write {
+    fn size(&self) -> io::Result<(usize, usize)>;
+
+    // Waits at most `timeout` for input, returning 0 if none arrived.
+    fn read_input(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+
+    // Returns whether the size changed since the last call.
+    fn resized(&mut self) -> bool { false }
+}
+
+// What the application should do after handling a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    Continue,
+    Quit,
+}
+
+type MessageHandler<'a, E> = Box<dyn FnMut(&mut E, Message) -> Control + 'a>;
+
+// How long to wait for the rest of an escape sequence before treating ESC as a key.
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+pub struct App<'a, E, B>
+    where E: Element<'a>,
+          B: Backend
+{
+    root: E,
+    backend: B,
+    canvas: Canvas,
+    renderer: Renderer,
+    keymap: Keymap,
+    tick_rate: Duration,
+    on_message: MessageHandler<'a, E>,
+    _a: PhantomData<&'a ()>,
+}
+
+impl<'a, E, B> App<'a, E, B>
+    where E: Element<'a>,
+          B: Backend
+{
+    pub fn new(mut root: E, backend: B) -> io::Result<App<'a, E, B>> {
+        let (width, height) = backend.size()?;
+        root.resize(width, height);
+        Ok(App {
+            root, backend,
+            canvas: Canvas::new(width, height, ' '),
+            renderer: Renderer::new(),
+            keymap: Keymap::default(),
+            tick_rate: Duration::from_millis(100),
+            on_message: Box::new(|_, _| Control::Continue),
+            _a: PhantomData,
+        })
+    }
+
+    pub fn tick_rate(mut self, tick_rate: Duration) -> App<'a, E, B> {
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    pub fn keymap(mut self, keymap: Keymap) -> App<'a, E, B> {
+        self.keymap = keymap;
+        self
+    }
+
+    // Sets what to do with messages that make it out of the root element.
+    pub fn on_message<F>(mut self, handler: F) -> App<'a, E, B>
+        where F: FnMut(&mut E, Message) -> Control + 'a
+    {
+        self.on_message = Box::new(handler);
+        self
+    }
+
+    // Runs until the quit action is pressed and the root element does not handle
+    // it, then gives back the root element.
+    pub fn run(mut self) -> io::Result<E> {
+        let mut parser = Parser::new();
+        let mut buf = [0; 1024];
+        let mut next_tick = Instant::now();
+
+        loop {
+            if self.backend.resized() {
+                let (width, height) = self.backend.size()?;
+                self.canvas.resize(width, height, ' ');
+                self.canvas.clear(' ');
+                self.renderer.invalidate();
+                self.root.resize(width, height);
+                self.root.draw(&mut self.canvas.view(), true);
+                self.renderer.render(&self.canvas, &mut self.backend)?;
+            }
+
+            let now = Instant::now();
+            if now >= next_tick {
+                self.root.draw_advance(&mut self.canvas.view(), true);
+                self.renderer.render(&self.canvas, &mut self.backend)?;
+                next_tick = now + self.tick_rate;
+            }
+
+            let now = Instant::now();
+            let mut timeout = if next_tick > now {
+                next_tick - now
+            } else {
+                Duration::from_secs(0)
+            };
+            if parser.is_pending() {
+                timeout = timeout.min(ESCAPE_TIMEOUT);
+            }
+            let len = self.backend.read_input(&mut buf, timeout)?;
+            parser.feed(&buf[..len]);
+
+            let mut redraw = false;
+            loop {
+                let event = if len == 0 {
+                    parser.flush()
+                } else {
+                    parser.next_event()
+                };
+                let event = match event {
+                    Some(event) => event,
+                    None => break,
+                };
+                match self.root.respond_event(&event, &self.keymap) {
+                    // Elements get the first chance at the quit key, so one
+                    // taking text can still receive it.
+                    Response::Nothing => if self.keymap.action(&event) == Some(Action::Quit) {
+                        return Ok(self.root);
+                    },
+                    // Nothing is left to move to, so start over from the other end.
+                    Response::FocusNext => {
+                        self.root.focus_lost();
+                        self.root.enter_first();
+                        self.root.focus_gained();
+                        redraw = true
+                    },
+                    Response::FocusPrev => {
+                        self.root.focus_lost();
+                        self.root.enter_last();
+                        self.root.focus_gained();
+                        redraw = true
+                    },
+                    Response::Message(message) => {
+                        if (self.on_message)(&mut self.root, message) == Control::Quit {
+                            return Ok(self.root);
+                        }
+                        redraw = true
+                    },
+                    _ => redraw = true,
+                }
+            }
+            if redraw {
+                self.root.draw(&mut self.canvas.view(), true);
+                self.renderer.render(&self.canvas, &mut self.backend)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::{CanvasView, TextStyles};
+    use crate::input::{Event, KeyCode, KeyEvent, Modifiers};
+
+    use std::collections::VecDeque;
+
+    struct FakeBackend {
+        input: VecDeque<&'static [u8]>,
+        output: Vec<u8>,
+        size: (usize, usize),
+        resized: bool,
+    }
+
+    impl Write for FakeBackend {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    impl Backend for FakeBackend {
+        fn size(&self) -> io::Result<(usize, usize)> { Ok(self.size) }
+
+        fn read_input(&mut self, buf: &mut [u8], _timeout: Duration) -> io::Result<usize> {
+            let chunk = self.input.pop_front().expect("the app should have quit");
+            if chunk == b"RESIZE" {
+                self.size = (6, 2);
+                self.resized = true;
+                return Ok(0);
+            }
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+
+        fn resized(&mut self) -> bool {
+            let resized = self.resized;
+            self.resized = false;
+            resized
+        }
+    }
+
+    struct Counter {
+        presses: usize,
+        ticks: usize,
+        size: (usize, usize),
+    }
+
+    impl<'a> Element<'a> for Counter {
+        fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+            canvas.text(&self.presses.to_string(), 0, 0, TextStyles::new())
+        }
+
+        fn advance(&mut self) {
+            self.ticks += 1
+        }
+
+        fn resize(&mut self, width: usize, height: usize) {
+            self.size = (width, height)
+        }
+
+        fn respond_action(&mut self, action: Action) -> Response {
+            match action {
+                Action::Activate => {
+                    self.presses += 1;
+                    Response::Contained
+                },
+                Action::Cancel => Response::Message(Message::new(self.presses)),
+                _ => Response::Nothing,
+            }
+        }
+    }
+
+    #[test]
+    fn running_works() {
+        let backend = FakeBackend {
+            input: vec![&b"\r"[..], b"\r\r", b"", b"x\x1B", b"", b"q"].into_iter().collect(),
+            output: Vec::new(),
+            size: (4, 1),
+            resized: false,
+        };
+        let app = App::new(Counter { presses: 0, ticks: 0, size: (0, 0) }, backend).unwrap()
+            .tick_rate(Duration::from_secs(3600));
+        let counter = app.run().unwrap();
+        assert_eq!(counter.presses, 3);
+        assert_eq!(counter.ticks, 1);
+        assert_eq!(counter.size, (4, 1));
+    }
+
+    // Takes every plain character as text.
+    struct Field(String);
+
+    impl<'a> Element<'a> for Field {
+        fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+            canvas.text(&self.0, 0, 0, TextStyles::new())
+        }
+
+        fn advance(&mut self) {}
+
+        fn respond_event(&mut self, event: &Event, _keymap: &Keymap) -> Response {
+            match *event {
+                Event::Key(KeyEvent { code: KeyCode::Char(ch), mods }) if mods == Modifiers::NONE => {
+                    self.0.push(ch);
+                    Response::Contained
+                },
+                _ => Response::Nothing,
+            }
+        }
+    }
+
+    #[test]
+    fn elements_can_take_the_quit_key() {
+        let backend = FakeBackend {
+            input: vec![&b"quiq"[..], b"\x03"].into_iter().collect(),
+            output: Vec::new(),
+            size: (4, 1),
+            resized: false,
+        };
+        let field = App::new(Field(String::new()), backend).unwrap().keymap(Keymap::vi()).run().unwrap();
+        assert_eq!(field.0, "quiq");
+    }
+
+    #[test]
+    fn messages_reach_the_app() {
+        let backend = FakeBackend {
+            input: vec![&b"\r"[..], b"\x1B", b"", b"\r\x1B", b""].into_iter().collect(),
+            output: Vec::new(),
+            size: (4, 1),
+            resized: false,
+        };
+        let mut received = Vec::new();
+        let counter = App::new(Counter { presses: 0, ticks: 0, size: (0, 0) }, backend).unwrap()
+            .on_message(|counter, message| {
+                received.push(*message.downcast_ref::<usize>().unwrap());
+                if counter.presses == 2 { Control::Quit } else { Control::Continue }
+            })
+            .run()
+            .unwrap();
+        assert_eq!(counter.presses, 2);
+        assert_eq!(received, vec![1, 2]);
+    }
+
+    #[test]
+    fn resizing_works() {
+        let backend = FakeBackend {
+            input: vec![&b"RESIZE"[..], b"q"].into_iter().collect(),
+            output: Vec::new(),
+            size: (4, 1),
+            resized: false,
+        };
+        let app = App::new(Counter { presses: 0, ticks: 0, size: (0, 0) }, backend).unwrap();
+        assert_eq!(app.root.size, (4, 1));
+        let counter = app.run().unwrap();
+        assert_eq!(counter.size, (6, 2));
+    }
+}
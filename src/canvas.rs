@@ -1,5 +1,9 @@
-use std::fmt::{self, Display, Formatter};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use std::fmt::{self, Display, Formatter, Write};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Canvas {
     width: usize,
     height: usize,
@@ -10,7 +14,33 @@ impl Canvas {
      pub fn new(width: usize, height: usize, filler: char) -> Canvas {
         Canvas {
             width, height,
-            pixels: vec![Pixel { ch: filler, flags: 0, }; width * height],
+            pixels: vec![Pixel::new(filler, TextStyles::new()); width * height],
+        }
+    }
+
+    // Keeps the pixels that are still in bounds and fills new space with `filler`.
+    pub fn resize(&mut self, width: usize, height: usize, filler: char) {
+        let blank = Pixel::new(filler, TextStyles::new());
+        let mut pixels = vec![blank.clone(); width * height];
+        let len = width.min(self.width);
+        for y in 0..height.min(self.height) {
+            let row = &mut pixels[y * width .. y * width + len];
+            row.clone_from_slice(&self.pixels[y * self.width .. y * self.width + len]);
+            // A wide character cut in half by the new right edge cannot be shown.
+            if let Some(last) = row.last_mut() {
+                if last.width() > 1 {
+                    *last = blank.clone();
+                }
+            }
+        }
+        self.width = width;
+        self.height = height;
+        self.pixels = pixels;
+    }
+
+    pub fn clear(&mut self, filler: char) {
+        for p in &mut self.pixels {
+            *p = Pixel::new(filler, TextStyles::new());
         }
     }
 
@@ -18,6 +48,15 @@ impl Canvas {
 
     pub fn height(&self) -> usize { self.height }
 
+    pub fn view(&mut self) -> CanvasView<'_> {
+        let (width, height) = (self.width, self.height);
+        CanvasView {
+            canvas: self,
+            x: 0, y: 0,
+            width, height,
+        }
+    }
+
     pub fn get(&self, x: usize, y: usize) -> Option<&Pixel> {
         if x < self.width && y < self.height {
             Some(unsafe {
@@ -38,99 +77,434 @@ impl Canvas {
         }
     }
     
+    /// # Safety
+    ///
+    /// The position must be within the canvas.
     pub unsafe fn get_unchecked(&self, x: usize, y: usize) -> &Pixel {
         self.pixels.get_unchecked(y * self.width + x)
     }
     
+    /// # Safety
+    ///
+    /// The position must be within the canvas.
     pub unsafe fn get_unchecked_mut(&mut self, x: usize, y: usize) -> &mut Pixel {
         self.pixels.get_unchecked_mut(y * self.width + x)
     }
 
     pub fn text(&mut self, text: &str, x: usize, y: usize, styles: TextStyles) {
-        let mut current_x = x; let mut current_y = y;
-        if x >= self.width || y >= self.height {
-            return;
+        for (i, line) in text.split('\n').enumerate() {
+            if y + i >= self.height {
+                break;
+            }
+            self.write_line(line, x, y + i, usize::MAX, styles);
         }
-        let mut in_bounds = true;
-        let mut last_x = x; let mut last_y = y;
+    }
 
-        unsafe {
-            self.get_unchecked_mut(current_x, current_y).set_styles_on(styles);
+    // Writes one line of text, stopping before it would take up more than
+    // `max_width` columns or cross the right edge. Returns the columns used.
+    pub fn write_line(&mut self, text: &str, x: usize, y: usize, max_width: usize, styles: TextStyles) -> usize {
+        if x >= self.width || y >= self.height {
+            return 0;
         }
-
-        for letter in text.chars() {
-            match letter {
-                '\n' => {
-                    unsafe {
-                        self.get_unchecked_mut(last_x, last_y).set_styles_off(styles);
-                    }
-                    current_x = x;
-                    current_y += 1;
-                    in_bounds = current_x < self.width && current_y < self.height;
-                    if in_bounds {
-                        unsafe {
-                            self.get_unchecked_mut(current_x, current_y).set_styles_on(styles);
-                        }
-                        last_x = current_x;
-                        last_y = current_y;
-                    }
-                },
-                letter => {
-                    if in_bounds {
-                        unsafe {
-                            self.get_unchecked_mut(current_x, current_y).ch = letter;
-                        }
-                        last_x = current_x;
-                        last_y = current_y;
-                        current_x += 1;
-                        in_bounds = self.width > current_x;
+        let end = x + max_width.min(self.width - x);
+        let mut current_x = x;
+
+        for grapheme in text.graphemes(true) {
+            let grapheme = printable(grapheme);
+            let width = grapheme_width(grapheme);
+            if width == 0 {
+                // Zero-width characters combine with whatever came just before them.
+                if current_x > x {
+                    let mut lead = current_x - 1;
+                    while lead > x && unsafe { self.get_unchecked(lead, y) }.continuation {
+                        lead -= 1;
                     }
+                    unsafe { self.get_unchecked_mut(lead, y) }.combining.push_str(grapheme);
                 }
+                continue;
+            }
+            if current_x + width > end {
+                break;
             }
+            current_x += self.put(grapheme, current_x, y, styles);
+        }
+        current_x - x
+    }
+
+    // Puts a single grapheme cluster at a position, taking up as many columns as
+    // it is wide. Returns the number of columns used, which is zero if it did not fit.
+    pub fn put(&mut self, grapheme: &str, x: usize, y: usize, styles: TextStyles) -> usize {
+        let grapheme = printable(grapheme);
+        let width = grapheme_width(grapheme).max(1);
+        if y >= self.height || x >= self.width || width > self.width - x {
+            return 0;
         }
 
+        // Never leave half of a wide character behind.
+        if unsafe { self.get_unchecked(x, y) }.continuation && x > 0 {
+            let lead = unsafe { self.get_unchecked_mut(x - 1, y) };
+            *lead = Pixel::new(' ', lead.styles);
+        }
+        if let Some(after) = self.get_mut(x + width, y) {
+            if after.continuation {
+                *after = Pixel::new(' ', after.styles);
+            }
+        }
+
+        let mut chars = grapheme.chars();
+        let mut lead = Pixel::new(chars.next().unwrap_or(' '), styles);
+        lead.combining.extend(chars);
         unsafe {
-            self.get_unchecked_mut(last_x, last_y).set_styles_off(styles);
+            *self.get_unchecked_mut(x, y) = lead;
         }
+        for tail_x in x + 1 .. x + width {
+            let mut tail = Pixel::new(' ', styles);
+            tail.continuation = true;
+            unsafe {
+                *self.get_unchecked_mut(tail_x, y) = tail;
+            }
+        }
+        width
     }
 
     pub fn line(&mut self, fill: char, x: usize, y: usize, len: usize, styles: TextStyles) {
         if x >= self.width || y >= self.height || len == 0 {
             return;
         }
-        let len = if x + len >= self.width {
-            self.width - x
-        } else {
-            len
+        let end = x + len.min(self.width - x);
+        let mut fill_buf = [0; 4];
+        let fill = printable(fill.encode_utf8(&mut fill_buf));
+        let mut current_x = x;
+
+        while current_x < end {
+            if current_x + grapheme_width(fill).max(1) > end {
+                current_x += self.put(" ", current_x, y, styles);
+            } else {
+                current_x += self.put(fill, current_x, y, styles);
+            }
+        }
+    }
+
+    pub fn vline(&mut self, fill: char, x: usize, y: usize, len: usize, styles: TextStyles) {
+        self.view().vline(fill, x, y, len, styles)
+    }
+
+    // Draws the outline of a rectangle with one character.
+    pub fn rect(&mut self, fill: char, x: usize, y: usize, width: usize, height: usize, styles: TextStyles) {
+        self.view().rect(fill, x, y, width, height, styles)
+    }
+
+    pub fn fill_rect(&mut self, fill: char, x: usize, y: usize, width: usize, height: usize, styles: TextStyles) {
+        self.view().fill_rect(fill, x, y, width, height, styles)
+    }
+
+    // Draws a horizontal box-drawing line, joining up with any lines it meets.
+    pub fn hrule(&mut self, x: usize, y: usize, len: usize, style: BoxStyle, styles: TextStyles) {
+        self.view().hrule(x, y, len, style, styles)
+    }
+
+    pub fn vrule(&mut self, x: usize, y: usize, len: usize, style: BoxStyle, styles: TextStyles) {
+        self.view().vrule(x, y, len, style, styles)
+    }
+
+    pub fn frame(&mut self, x: usize, y: usize, width: usize, height: usize, style: BoxStyle, styles: TextStyles) {
+        self.view().frame(x, y, width, height, style, styles)
+    }
+
+    // Adds arms to the box-drawing character at a position, or replaces whatever
+    // else is there.
+    fn put_arms(&mut self, x: usize, y: usize, arms: usize, style: BoxStyle, styles: TextStyles) {
+        let existing = match self.get(x, y) {
+            Some(p) if !p.continuation && p.combining.is_empty() => box_arms(p.ch),
+            Some(_) => 0,
+            None => return,
         };
+        let mut buf = [0; 4];
+        self.put(style.chars()[existing | arms].encode_utf8(&mut buf), x, y, styles);
+    }
+}
 
-        for p in &mut self.pixels[y * self.width + x .. y * self.width + x + len] {
-            p.ch = fill
+// A rectangle of a canvas with its own coordinates. Nothing drawn through a view
+// lands outside of it, except that wide characters cut in half at its edges are
+// blanked.
+pub struct CanvasView<'c> {
+    canvas: &'c mut Canvas,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'c> CanvasView<'c> {
+    pub fn width(&self) -> usize { self.width }
+
+    pub fn height(&self) -> usize { self.height }
+
+    // Narrows the view further. The result is clipped to this view's bounds.
+    pub fn view(&mut self, x: usize, y: usize, width: usize, height: usize) -> CanvasView<'_> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        CanvasView {
+            canvas: self.canvas,
+            x: self.x + x,
+            y: self.y + y,
+            width: width.min(self.width - x),
+            height: height.min(self.height - y),
         }
+    }
 
-        unsafe {
-            self.get_unchecked_mut(x, y).set_styles_on(styles);
-            self.get_unchecked_mut(x + len - 1, y).set_styles_off(styles)
+    pub fn get(&self, x: usize, y: usize) -> Option<&Pixel> {
+        if x < self.width && y < self.height {
+            self.canvas.get(self.x + x, self.y + y)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Pixel> {
+        if x < self.width && y < self.height {
+            self.canvas.get_mut(self.x + x, self.y + y)
+        } else {
+            None
+        }
+    }
+
+    pub fn text(&mut self, text: &str, x: usize, y: usize, styles: TextStyles) {
+        for (i, line) in text.split('\n').enumerate() {
+            if y + i >= self.height {
+                break;
+            }
+            self.write_line(line, x, y + i, usize::MAX, styles);
+        }
+    }
+
+    pub fn write_line(&mut self, text: &str, x: usize, y: usize, max_width: usize, styles: TextStyles) -> usize {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        self.canvas.write_line(text, self.x + x, self.y + y, max_width.min(self.width - x), styles)
+    }
+
+    pub fn put(&mut self, grapheme: &str, x: usize, y: usize, styles: TextStyles) -> usize {
+        let width = grapheme_width(printable(grapheme)).max(1);
+        if x >= self.width || width > self.width - x || y >= self.height {
+            return 0;
+        }
+        self.canvas.put(grapheme, self.x + x, self.y + y, styles)
+    }
+
+    pub fn line(&mut self, fill: char, x: usize, y: usize, len: usize, styles: TextStyles) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        self.canvas.line(fill, self.x + x, self.y + y, len.min(self.width - x), styles)
+    }
+
+    pub fn vline(&mut self, fill: char, x: usize, y: usize, len: usize, styles: TextStyles) {
+        let mut fill_buf = [0; 4];
+        let fill = &*fill.encode_utf8(&mut fill_buf);
+        for y in y..y.saturating_add(len).min(self.height) {
+            self.put(fill, x, y, styles);
+        }
+    }
+
+    pub fn rect(&mut self, fill: char, x: usize, y: usize, width: usize, height: usize, styles: TextStyles) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.line(fill, x, y, width, styles);
+        self.line(fill, x, y + height - 1, width, styles);
+        self.vline(fill, x, y, height, styles);
+        self.vline(fill, x + width - 1, y, height, styles);
+    }
+
+    pub fn fill_rect(&mut self, fill: char, x: usize, y: usize, width: usize, height: usize, styles: TextStyles) {
+        for y in y..y.saturating_add(height).min(self.height) {
+            self.line(fill, x, y, width, styles);
+        }
+    }
+
+    pub fn hrule(&mut self, x: usize, y: usize, len: usize, style: BoxStyle, styles: TextStyles) {
+        for i in 0..len.min(self.width.saturating_sub(x)) {
+            // The ends only reach inwards so that they make tees where they meet other lines.
+            let left = if i > 0 || len == 1 { LEFT } else { 0 };
+            let right = if i + 1 < len || len == 1 { RIGHT } else { 0 };
+            self.put_arms(x + i, y, left | right, style, styles);
+        }
+    }
+
+    pub fn vrule(&mut self, x: usize, y: usize, len: usize, style: BoxStyle, styles: TextStyles) {
+        for i in 0..len.min(self.height.saturating_sub(y)) {
+            let up = if i > 0 || len == 1 { UP } else { 0 };
+            let down = if i + 1 < len || len == 1 { DOWN } else { 0 };
+            self.put_arms(x, y + i, up | down, style, styles);
+        }
+    }
+
+    pub fn frame(&mut self, x: usize, y: usize, width: usize, height: usize, style: BoxStyle, styles: TextStyles) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        // Frames too thin to have corners are just lines.
+        if height == 1 {
+            return self.hrule(x, y, width, style, styles);
+        }
+        if width == 1 {
+            return self.vrule(x, y, height, style, styles);
+        }
+        let (right, bottom) = (x + width - 1, y + height - 1);
+        self.put_arms(x, y, DOWN | RIGHT, style, styles);
+        self.put_arms(right, y, DOWN | LEFT, style, styles);
+        self.put_arms(x, bottom, UP | RIGHT, style, styles);
+        self.put_arms(right, bottom, UP | LEFT, style, styles);
+        for x in x + 1..right {
+            self.put_arms(x, y, LEFT | RIGHT, style, styles);
+            self.put_arms(x, bottom, LEFT | RIGHT, style, styles);
+        }
+        for y in y + 1..bottom {
+            self.put_arms(x, y, UP | DOWN, style, styles);
+            self.put_arms(right, y, UP | DOWN, style, styles);
+        }
+    }
+
+    fn put_arms(&mut self, x: usize, y: usize, arms: usize, style: BoxStyle, styles: TextStyles) {
+        if x < self.width && y < self.height {
+            self.canvas.put_arms(self.x + x, self.y + y, arms, style, styles)
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BoxStyle {
+    Single,
+    Double,
+    Rounded,
+    Heavy,
+    // For terminals without box-drawing characters.
+    Ascii,
+}
+
+impl BoxStyle {
+    fn chars(self) -> &'static [char; 16] {
+        match self {
+            BoxStyle::Single  => &SINGLE_BOX,
+            BoxStyle::Double  => &DOUBLE_BOX,
+            BoxStyle::Rounded => &ROUNDED_BOX,
+            BoxStyle::Heavy   => &HEAVY_BOX,
+            BoxStyle::Ascii   => &ASCII_BOX,
+        }
+    }
+}
+
+// The directions a box-drawing character reaches in, which index the tables below.
+const UP: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 4;
+const RIGHT: usize = 8;
+
+const SINGLE_BOX: [char; 16] =
+    [' ', '│', '│', '│', '─', '┘', '┐', '┤', '─', '└', '┌', '├', '─', '┴', '┬', '┼'];
+const DOUBLE_BOX: [char; 16] =
+    [' ', '║', '║', '║', '═', '╝', '╗', '╣', '═', '╚', '╔', '╠', '═', '╩', '╦', '╬'];
+const ROUNDED_BOX: [char; 16] =
+    [' ', '│', '│', '│', '─', '╯', '╮', '┤', '─', '╰', '╭', '├', '─', '┴', '┬', '┼'];
+const HEAVY_BOX: [char; 16] =
+    [' ', '┃', '┃', '┃', '━', '┛', '┓', '┫', '━', '┗', '┏', '┣', '━', '┻', '┳', '╋'];
+const ASCII_BOX: [char; 16] =
+    [' ', '|', '|', '|', '-', '+', '+', '+', '-', '+', '+', '+', '-', '+', '+', '+'];
+
+// Finds the arms of a box-drawing character, or 0 if it is not one. Lines are
+// taken to reach both ways, and ASCII corners every way.
+fn box_arms(ch: char) -> usize {
+    [SINGLE_BOX, DOUBLE_BOX, ROUNDED_BOX, HEAVY_BOX, ASCII_BOX].iter()
+        .filter_map(|chars| chars.iter().rposition(|&c| c == ch))
+        .max()
+        .unwrap_or(0)
+}
+
+// Terminals draw any single grapheme cluster in at most two columns.
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
+}
+
+// Control characters would move the terminal's cursor instead of drawing
+// anything, so they are drawn as spaces.
+fn printable(grapheme: &str) -> &str {
+    if grapheme.chars().any(char::is_control) { " " } else { grapheme }
+}
+
 impl Display for Canvas {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         for ps in self.pixels.chunks(self.width) {
-            for p in ps {
-                write!(f, "{}", p)?;
+            let mut current = TextStyles::new();
+            for p in ps.iter().filter(|p| !p.continuation) {
+                current.write_transition(p.styles, f)?;
+                current = p.styles;
+                p.write_text(f)?;
             }
-            write!(f, "\x1B[0m\n")?;
+            current.write_transition(TextStyles::new(), f)?;
+            writeln!(f)?;
         }
 
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TextStyles {
     inner: u8,
+    fg: Color,
+    bg: Color,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Color {
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn write_params<W: Write>(self, f: &mut W, base: u8) -> fmt::Result {
+        // base is 30 for the foreground and 40 for the background.
+        match self {
+            Color::Default       => write!(f, "{}", base + 9),
+            Color::Black         => write!(f, "{}", base),
+            Color::Red           => write!(f, "{}", base + 1),
+            Color::Green         => write!(f, "{}", base + 2),
+            Color::Yellow        => write!(f, "{}", base + 3),
+            Color::Blue          => write!(f, "{}", base + 4),
+            Color::Magenta       => write!(f, "{}", base + 5),
+            Color::Cyan          => write!(f, "{}", base + 6),
+            Color::White         => write!(f, "{}", base + 7),
+            Color::BrightBlack   => write!(f, "{}", base + 60),
+            Color::BrightRed     => write!(f, "{}", base + 61),
+            Color::BrightGreen   => write!(f, "{}", base + 62),
+            Color::BrightYellow  => write!(f, "{}", base + 63),
+            Color::BrightBlue    => write!(f, "{}", base + 64),
+            Color::BrightMagenta => write!(f, "{}", base + 65),
+            Color::BrightCyan    => write!(f, "{}", base + 66),
+            Color::BrightWhite   => write!(f, "{}", base + 67),
+            Color::Indexed(i)    => write!(f, "{};5;{}", base + 8, i),
+            Color::Rgb(r, g, b)  => write!(f, "{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
 }
 
 const BOLD_POS: u8 = 0;
@@ -138,124 +512,318 @@ const ITALICS_POS: u8 = 1;
 const UNDERLINE_POS: u8 = 2;
 const INVERSE_POS: u8 = 3;
 
-const BOLD_ON: u8 = 1 << BOLD_POS;
-const ITALICS_ON: u8 = 1 << ITALICS_POS;
-const UNDERLINE_ON: u8 = 1 << UNDERLINE_POS;
-const INVERSE_ON: u8 = 1 << INVERSE_POS;
-const BOLD_OFF: u8 = 1 << (BOLD_POS + 4);
-const ITALICS_OFF: u8 = 1 << (ITALICS_POS + 4);
-const UNDERLINE_OFF: u8 = 1 << (UNDERLINE_POS + 4);
-const INVERSE_OFF: u8 = 1 << (INVERSE_POS + 4);
+// SGR parameters turning each style on and off, indexed by position.
+const STYLE_ON_CODES: [u8; 4] = [1, 3, 4, 7];
+const STYLE_OFF_CODES: [u8; 4] = [22, 23, 24, 27];
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Pixel {
     pub ch: char,
-    pub flags: u8,
+    // Zero-width characters, such as combining marks, drawn on top of `ch`.
+    pub combining: String,
+    pub styles: TextStyles,
+    // Whether this is covered by the wide character in the pixel to its left.
+    pub continuation: bool,
 }
 
 impl Pixel {
-    pub fn set_styles_on(&mut self, styles: TextStyles) {
-        self.flags &= !0 << 4;
-        self.flags |= styles.inner;
+    pub fn new(ch: char, styles: TextStyles) -> Pixel {
+        Pixel {
+            ch, styles,
+            combining: String::new(),
+            continuation: false,
+        }
     }
 
-    pub fn set_styles_off(&mut self, styles: TextStyles) {
-        self.flags &= !0 >> 4;
-        self.flags |= styles.inner << 4;
+    // The number of columns the pixel's text covers, counting itself.
+    pub fn width(&self) -> usize {
+        if self.continuation {
+            0
+        } else {
+            let mut text = String::new();
+            let _ = self.write_text(&mut text);
+            grapheme_width(&text).max(1)
+        }
+    }
+
+    pub fn write_text<W: Write>(&self, out: &mut W) -> fmt::Result {
+        if !self.continuation {
+            out.write_char(self.ch)?;
+            out.write_str(&self.combining)?;
+        }
+        Ok(())
     }
 }
 
 impl Display for Pixel {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        if self.flags == 0 {
-            write!(f, "{}", self.ch)
-        } else {
-            if self.flags & BOLD_ON != 0 {
-                write!(f, "\x1B[1m")?;
-            }
-            if self.flags & ITALICS_ON != 0 {
-                write!(f, "\x1B[3m")?;
-            }
-            if self.flags & UNDERLINE_ON != 0 {
-                write!(f, "\x1B[4m")?;
-            }
-            if self.flags & INVERSE_ON != 0 {
-                write!(f, "\x1B[7m")?;
-            }
-            write!(f, "{}", self.ch)?;
-            if self.flags & BOLD_OFF != 0 {
-                write!(f, "\x1B[22m")?;
-            }
-            if self.flags & ITALICS_OFF != 0 {
-                write!(f, "\x1B[23m")?;
-            }
-            if self.flags & UNDERLINE_OFF != 0 {
-                write!(f, "\x1B[24m")?;
-            }
-            if self.flags & INVERSE_OFF != 0 {
-                write!(f, "\x1B[27m")?;
-            }
+        TextStyles::new().write_transition(self.styles, f)?;
+        self.write_text(f)?;
+        self.styles.write_transition(TextStyles::new(), f)
+    }
+}
 
-            Ok(())
-        }
+impl Default for TextStyles {
+    fn default() -> TextStyles {
+        TextStyles::new()
     }
 }
 
 impl TextStyles {
     pub fn new() -> TextStyles {
-        TextStyles { inner: 0 }
+        TextStyles { inner: 0, fg: Color::Default, bg: Color::Default }
     }
 
-    pub fn bold(mut self, yes: bool) -> TextStyles {
-        self.inner |= (yes as u8) << BOLD_POS;
-        self
+    pub fn bold(self, yes: bool) -> TextStyles {
+        self.with_style(BOLD_POS, yes)
+    }
+
+    pub fn italics(self, yes: bool) -> TextStyles {
+        self.with_style(ITALICS_POS, yes)
     }
 
-    pub fn italics(mut self, yes: bool) -> TextStyles {
-        self.inner |= (yes as u8) << ITALICS_POS;
+    pub fn underline(self, yes: bool) -> TextStyles {
+        self.with_style(UNDERLINE_POS, yes)
+    }
+
+    pub fn inverse(self, yes: bool) -> TextStyles {
+        self.with_style(INVERSE_POS, yes)
+    }
+
+    pub fn is_bold(&self) -> bool { self.has_style(BOLD_POS) }
+
+    pub fn is_italics(&self) -> bool { self.has_style(ITALICS_POS) }
+
+    pub fn is_underline(&self) -> bool { self.has_style(UNDERLINE_POS) }
+
+    pub fn is_inverse(&self) -> bool { self.has_style(INVERSE_POS) }
+
+    pub fn fg_color(&self) -> Color { self.fg }
+
+    pub fn bg_color(&self) -> Color { self.bg }
+
+    pub fn fg(mut self, color: Color) -> TextStyles {
+        self.fg = color;
         self
     }
 
-    pub fn underline(mut self, yes: bool) -> TextStyles {
-        self.inner |= (yes as u8) << UNDERLINE_POS;
+    pub fn bg(mut self, color: Color) -> TextStyles {
+        self.bg = color;
         self
     }
 
-    pub fn inverse(mut self, yes: bool) -> TextStyles {
-        self.inner |= (yes as u8) << INVERSE_POS;
+    // Writes the shortest SGR sequence changing the terminal from these styles to `to`.
+    pub fn write_transition<W: Write>(&self, to: TextStyles, f: &mut W) -> fmt::Result {
+        if *self == to {
+            return Ok(());
+        }
+        if to == TextStyles::new() {
+            return write!(f, "\x1B[0m");
+        }
+
+        write!(f, "\x1B[")?;
+        let mut first = true;
+        for pos in 0..4 {
+            if self.has_style(pos) != to.has_style(pos) {
+                if !first {
+                    write!(f, ";")?;
+                }
+                first = false;
+                let codes = if to.has_style(pos) { STYLE_ON_CODES } else { STYLE_OFF_CODES };
+                write!(f, "{}", codes[pos as usize])?;
+            }
+        }
+        if self.fg != to.fg {
+            if !first {
+                write!(f, ";")?;
+            }
+            first = false;
+            to.fg.write_params(f, 30)?;
+        }
+        if self.bg != to.bg {
+            if !first {
+                write!(f, ";")?;
+            }
+            to.bg.write_params(f, 40)?;
+        }
+        write!(f, "m")
+    }
+
+    fn with_style(mut self, pos: u8, yes: bool) -> TextStyles {
+        self.inner &= !(1 << pos);
+        self.inner |= (yes as u8) << pos;
         self
     }
+
+    fn has_style(&self, pos: u8) -> bool {
+        self.inner & (1 << pos) != 0
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::snapshot::assert_snapshot;
 
     #[test]
     fn out_of_bounds_works() {
         let mut c = Canvas::new(10, 10, '#');
         c.text("foo", 12, 12, TextStyles::new().underline(true));
-        println!("{}", c);
+        assert_snapshot("canvas_out_of_bounds", &c);
+        assert_eq!(c.put("x", usize::MAX, 0, TextStyles::new()), 0);
+        assert_eq!(c.view().put("x", usize::MAX, 0, TextStyles::new()), 0);
+        c.view().vline('|', usize::MAX, 0, 1, TextStyles::new());
+        assert_snapshot("canvas_out_of_bounds", &c);
     }
 
     #[test]
     fn overflowing_text_works() {
         let mut c = Canvas::new(10, 10, '#');
         c.text("foo", 8, 1, TextStyles::new().underline(true));
-        println!("{}", c);
+        assert_snapshot("canvas_overflowing_text", &c);
     }
 
     #[test]
     fn newlines_work() {
         let mut c = Canvas::new(10, 10, '#');
         c.text("\nfoo\n\nbar\n", 1, 1, TextStyles::new().underline(true));
-        println!("{}", c);
+        assert_snapshot("canvas_newlines", &c);
     }
 
     #[test]
     fn lines_work() {
         let mut c = Canvas::new(10, 10, '#');
         c.line('-', 1, 2, 11, TextStyles::new().inverse(true));
-        println!("{}", c);
+        assert_snapshot("canvas_lines", &c);
+    }
+
+    #[test]
+    fn shapes_work() {
+        let mut c = Canvas::new(6, 4, '.');
+        c.fill_rect('#', 1, 1, 3, 5, TextStyles::new());
+        c.rect('*', 2, 0, 9, 3, TextStyles::new());
+        c.vline('|', 0, 2, 1, TextStyles::new());
+        let rows: Vec<_> = c.to_string().lines().map(str::to_string).collect();
+        assert_eq!(rows, vec!["..****", ".#*#..", "|#****", ".###.."]);
+    }
+
+    #[test]
+    fn frames_work() {
+        let mut c = Canvas::new(24, 7, ' ');
+        // A table with a header row and two columns.
+        c.frame(0, 0, 7, 5, BoxStyle::Single, TextStyles::new());
+        c.hrule(0, 2, 7, BoxStyle::Single, TextStyles::new());
+        c.vrule(3, 0, 5, BoxStyle::Single, TextStyles::new());
+        c.frame(8, 0, 4, 3, BoxStyle::Double, TextStyles::new());
+        c.frame(13, 0, 4, 3, BoxStyle::Rounded, TextStyles::new());
+        c.frame(18, 0, 4, 3, BoxStyle::Heavy, TextStyles::new());
+        c.frame(8, 3, 4, 3, BoxStyle::Ascii, TextStyles::new());
+        c.hrule(8, 4, 6, BoxStyle::Ascii, TextStyles::new());
+        // Frames are clipped by views like everything else.
+        c.view().view(15, 4, 9, 3).frame(1, 1, 12, 3, BoxStyle::Single, TextStyles::new());
+        assert_snapshot("canvas_frames", &c);
+
+        // ASCII corners and junctions look alike, so they join up every way.
+        let mut c = Canvas::new(6, 3, ' ');
+        c.frame(0, 0, 6, 3, BoxStyle::Ascii, TextStyles::new());
+        c.vrule(3, 0, 3, BoxStyle::Ascii, TextStyles::new());
+        c.hrule(0, 2, 6, BoxStyle::Ascii, TextStyles::new());
+        assert_eq!(c.to_string(), "+--+-+\n|  | |\n+--+-+\n");
+    }
+
+    #[test]
+    fn resizing_works() {
+        let mut c = Canvas::new(3, 2, '#');
+        c.text("abc\ndef", 0, 0, TextStyles::new().bold(true));
+        c.resize(2, 3, '.');
+        assert_eq!((c.width(), c.height()), (2, 3));
+        assert_eq!(format!("{}", c), "\x1B[1mab\x1B[0m\n\x1B[1mde\x1B[0m\n..\n");
+        c.resize(3, 1, ' ');
+        assert_eq!(format!("{}", c), "\x1B[1mab\x1B[0m \n");
+        c.clear('-');
+        assert_eq!(format!("{}", c), "---\n");
+    }
+
+    #[test]
+    fn wide_characters_work() {
+        let mut c = Canvas::new(6, 2, '.');
+        c.text("漢字!", 0, 0, TextStyles::new());
+        assert_eq!(c.get(0, 0).unwrap().width(), 2);
+        assert!(c.get(1, 0).unwrap().continuation);
+        assert_eq!(format!("{}", c), "漢字!.\n......\n");
+
+        // Overwriting either half of a wide character blanks the other half.
+        c.text("x", 1, 0, TextStyles::new());
+        c.text("y", 2, 0, TextStyles::new());
+        assert_eq!(format!("{}", c), " xy !.\n......\n");
+
+        // Wide characters that would cross the edge are clipped whole.
+        c.text("ab漢", 3, 1, TextStyles::new());
+        assert_eq!(format!("{}", c), " xy !.\n...ab.\n");
+        assert_eq!(c.write_line("漢字", 0, 1, 3, TextStyles::new()), 2);
+        assert_eq!(format!("{}", c), " xy !.\n漢.ab.\n");
+    }
+
+    #[test]
+    fn combining_characters_work() {
+        let mut c = Canvas::new(4, 1, '.');
+        c.text("e\u{301}a\u{308}", 0, 0, TextStyles::new());
+        assert_eq!(c.get(0, 0).unwrap().combining, "\u{301}");
+        assert_eq!(format!("{}", c), "e\u{301}a\u{308}..\n");
+    }
+
+    #[test]
+    fn control_characters_are_replaced() {
+        let mut c = Canvas::new(6, 1, '.');
+        assert_eq!(c.write_line("a\tb\r\n\x1Bc", 0, 0, 6, TextStyles::new()), 6);
+        assert_eq!(format!("{}", c), "a b  c\n");
+        c.line('\x07', 0, 0, 2, TextStyles::new());
+        assert_eq!(format!("{}", c), "  b  c\n");
+    }
+
+    #[test]
+    fn views_clip_drawing() {
+        let mut c = Canvas::new(6, 4, '.');
+        {
+            let mut outer = c.view();
+            let mut inner = outer.view(1, 1, 3, 2);
+            assert_eq!((inner.width(), inner.height()), (3, 2));
+            inner.text("abcdef\nghijkl\nmnopqr", 0, 0, TextStyles::new());
+            inner.line('-', 2, 1, 10, TextStyles::new());
+            inner.text("漢", 2, 0, TextStyles::new());
+            assert_eq!(inner.get(0, 1).unwrap().ch, 'g');
+            assert!(inner.get(3, 0).is_none());
+
+            let mut corner = inner.view(2, 1, 5, 5);
+            assert_eq!((corner.width(), corner.height()), (1, 1));
+            corner.text("z", 0, 0, TextStyles::new());
+        }
+        assert_snapshot("canvas_views", &c);
+    }
+
+    #[test]
+    fn colors_work() {
+        let mut c = Canvas::new(10, 3, '#');
+        c.text("ok", 0, 0, TextStyles::new().fg(Color::Green));
+        c.text("bad", 0, 1, TextStyles::new().fg(Color::BrightRed).bg(Color::Indexed(236)));
+        c.line('=', 0, 2, 3, TextStyles::new().bg(Color::Rgb(1, 2, 3)));
+
+        assert_eq!(format!("{}", c.get(0, 0).unwrap()), "\x1B[32mo\x1B[0m");
+        assert_eq!(format!("{}", c.get(0, 1).unwrap()), "\x1B[91;48;5;236mb\x1B[0m");
+        assert_eq!(format!("{}", c.get(0, 2).unwrap()), "\x1B[48;2;1;2;3m=\x1B[0m");
+        assert_eq!(format!("{}", c.get(3, 0).unwrap()), "#");
+    }
+
+    #[test]
+    fn overwriting_styled_runs_works() {
+        let mut c = Canvas::new(6, 1, ' ');
+        c.text("abcdef", 0, 0, TextStyles::new().bold(true));
+        c.text("xy", 2, 0, TextStyles::new().bold(true).fg(Color::Red));
+        c.text("z", 5, 0, TextStyles::new());
+
+        assert!(c.get(1, 0).unwrap().styles.is_bold());
+        assert_eq!(c.get(3, 0).unwrap().styles.fg_color(), Color::Red);
+        assert_eq!(c.get(5, 0).unwrap().styles, TextStyles::new());
+        assert_eq!(format!("{}", c), "\x1B[1mab\x1B[31mxy\x1B[39me\x1B[0mz\n");
     }
 }
@@ -0,0 +1,213 @@
+use crate::{Element, ElemHandle, Response};
+use crate::canvas::Canvas;
+use crate::input::{Event, KeyEvent};
+use crate::keymap::Keymap;
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+// An owned copy of a Response that can be written into scripts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Nothing,
+    Contained,
+    MoveUp,
+    MoveDown,
+    MoveRight,
+    MoveLeft,
+    FocusNext,
+    FocusPrev,
+    Alert(Vec<ElemHandle>),
+    // Payloads cannot be compared, so only the path a message took is recorded.
+    Message(Vec<ElemHandle>),
+}
+
+impl From<Response> for Outcome {
+    fn from(response: Response) -> Outcome {
+        match response {
+            Response::Nothing   => Outcome::Nothing,
+            Response::Contained => Outcome::Contained,
+            Response::MoveUp    => Outcome::MoveUp,
+            Response::MoveDown  => Outcome::MoveDown,
+            Response::MoveRight => Outcome::MoveRight,
+            Response::MoveLeft  => Outcome::MoveLeft,
+            Response::FocusNext => Outcome::FocusNext,
+            Response::FocusPrev => Outcome::FocusPrev,
+            Response::Alert(a)  => Outcome::Alert(a),
+            Response::Message(m) => Outcome::Message(m.path().to_vec()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    Input(Event, Outcome),
+    Advance,
+    Frame(Vec<String>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn new() -> Script {
+        Script { steps: Vec::new() }
+    }
+
+    pub fn press<K: Into<KeyEvent>>(self, key: K, expected: Outcome) -> Script {
+        self.event(Event::Key(key.into()), expected)
+    }
+
+    pub fn event(mut self, event: Event, expected: Outcome) -> Script {
+        self.steps.push(Step::Input(event, expected));
+        self
+    }
+
+    pub fn advance(mut self) -> Script {
+        self.steps.push(Step::Advance);
+        self
+    }
+
+    // Draws the element and expects the characters on the canvas to be `rows`.
+    pub fn frame(mut self, rows: &[&str]) -> Script {
+        self.steps.push(Step::Frame(rows.iter().map(|r| r.to_string()).collect()));
+        self
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Error for Divergence {
+    fn description(&self) -> &str {
+        "A scripted step did not have the expected result"
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Step {} diverged.\nExpected:\n{}\nActual:\n{}", self.step, self.expected, self.actual)
+    }
+}
+
+pub struct Harness {
+    canvas: Canvas,
+    keymap: Keymap,
+}
+
+impl Harness {
+    pub fn new(width: usize, height: usize) -> Harness {
+        Harness {
+            canvas: Canvas::new(width, height, ' '),
+            keymap: Keymap::default(),
+        }
+    }
+
+    pub fn keymap(mut self, keymap: Keymap) -> Harness {
+        self.keymap = keymap;
+        self
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    // Runs each step in order, stopping at the first one that does not go as expected.
+    pub fn run<'a, E>(&mut self, root: &mut E, script: &Script) -> Result<(), Divergence>
+        where E: Element<'a> + ?Sized
+    {
+        for (i, step) in script.steps.iter().enumerate() {
+            match *step {
+                Step::Input(ref event, ref expected) => {
+                    let actual = Outcome::from(root.respond_event(event, &self.keymap));
+                    if actual != *expected {
+                        return Err(Divergence {
+                            step: i,
+                            expected: format!("{:?} after {:?}", expected, event),
+                            actual: format!("{:?} after {:?}", actual, event),
+                        });
+                    }
+                },
+                Step::Advance => root.advance(),
+                Step::Frame(ref rows) => {
+                    root.draw(&mut self.canvas.view(), true);
+                    let actual = self.rows();
+                    if actual != *rows {
+                        return Err(Divergence {
+                            step: i,
+                            expected: rows.join("\n"),
+                            actual: actual.join("\n"),
+                        });
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn rows(&self) -> Vec<String> {
+        (0..self.canvas.height()).map(|y| {
+            let mut row = String::new();
+            for x in 0..self.canvas.width() {
+                self.canvas.get(x, y).unwrap().write_text(&mut row).unwrap();
+            }
+            row
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Grid;
+    use crate::input::KeyCode;
+    use crate::util::TextScroller;
+
+    fn grid() -> Grid<'static> {
+        let mut grid = Grid::with_capacity(Box::new(TextScroller::new("a\nb\nc", 1, 2)), 0, 0,
+                                           Box::new(TextScroller::new("x\ny", 1, 2)), 2, 0, 0);
+        let left = grid.top_left();
+        let right = grid.bottom_right();
+        grid.connect_left_right(left, right).unwrap();
+        grid
+    }
+
+    #[test]
+    fn passing_scripts_work() {
+        let script = Script::new()
+            .frame(&["a x", "b y"])
+            .press('j', Outcome::Contained)
+            .frame(&["b x", "c y"])
+            .press('j', Outcome::MoveDown)
+            .press(KeyCode::Right, Outcome::Contained)
+            .press('l', Outcome::MoveRight)
+            .press('?', Outcome::Nothing)
+            .advance();
+        assert_eq!(Harness::new(3, 2).run(&mut grid(), &script), Ok(()));
+    }
+
+    #[test]
+    fn divergences_are_reported() {
+        let script = Script::new()
+            .press('h', Outcome::MoveLeft)
+            .press('k', Outcome::Contained);
+        let divergence = Harness::new(3, 2).run(&mut grid(), &script).unwrap_err();
+        assert_eq!(divergence.step, 1);
+        assert!(divergence.actual.starts_with("MoveUp"));
+
+        let script = Script::new().frame(&["a  ", "b  "]);
+        let divergence = Harness::new(3, 2).run(&mut grid(), &script).unwrap_err();
+        assert_eq!(divergence.step, 0);
+        assert_eq!(divergence.actual, "a x\nb y");
+    }
+}
@@ -0,0 +1,485 @@
+use std::ops::BitOr;
+use std::str;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Paste(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub mods: Modifiers,
+}
+
+impl KeyEvent {
+    pub fn new(code: KeyCode, mods: Modifiers) -> KeyEvent {
+        KeyEvent { code, mods }
+    }
+}
+
+impl From<KeyCode> for KeyEvent {
+    fn from(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, Modifiers::NONE)
+    }
+}
+
+impl From<char> for KeyEvent {
+    fn from(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), Modifiers::NONE)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+}
+
+// Coordinates are zero-based and relative to the element receiving the event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MouseEvent {
+    pub kind: MouseKind,
+    pub x: usize,
+    pub y: usize,
+    pub mods: Modifiers,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseKind {
+    Press(MouseButton),
+    Release(MouseButton),
+    Drag(MouseButton),
+    Move,
+    ScrollUp,
+    ScrollDown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    inner: u8,
+}
+
+const SHIFT_POS: u8 = 0;
+const ALT_POS: u8 = 1;
+const CTRL_POS: u8 = 2;
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { inner: 0 };
+    pub const SHIFT: Modifiers = Modifiers { inner: 1 << SHIFT_POS };
+    pub const ALT: Modifiers = Modifiers { inner: 1 << ALT_POS };
+    pub const CTRL: Modifiers = Modifiers { inner: 1 << CTRL_POS };
+
+    pub fn shift(self) -> bool { self.inner & Modifiers::SHIFT.inner != 0 }
+
+    pub fn alt(self) -> bool { self.inner & Modifiers::ALT.inner != 0 }
+
+    pub fn ctrl(self) -> bool { self.inner & Modifiers::CTRL.inner != 0 }
+
+    // Decodes the modifier parameter of xterm-style escape sequences.
+    fn from_param(param: u32) -> Modifiers {
+        let bits = param.saturating_sub(1);
+        let mut mods = Modifiers::NONE;
+        if bits & 1 != 0 {
+            mods = mods | Modifiers::SHIFT;
+        }
+        if bits & (2 | 8) != 0 {
+            mods = mods | Modifiers::ALT;
+        }
+        if bits & 4 != 0 {
+            mods = mods | Modifiers::CTRL;
+        }
+        mods
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        Modifiers { inner: self.inner | other.inner }
+    }
+}
+
+const ESC: u8 = 0x1B;
+const PASTE_END: &[u8] = b"\x1B[201~";
+// Longest escape sequence worth waiting for before giving up on it.
+const MAX_SEQUENCE: usize = 64;
+
+enum Parsed {
+    Event(Event, usize),
+    Skip(usize),
+    PasteStart(usize),
+    Incomplete,
+}
+
+pub struct Parser {
+    pending: Vec<u8>,
+    pasting: bool,
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            pending: Vec::new(),
+            pasting: false,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes)
+    }
+
+    pub fn next_event(&mut self) -> Option<Event> {
+        loop {
+            if self.pasting {
+                let end = self.pending.windows(PASTE_END.len()).position(|w| w == PASTE_END)?;
+                let text = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+                self.pending.drain(..end + PASTE_END.len());
+                self.pasting = false;
+                return Some(Event::Paste(text));
+            }
+            if self.pending.is_empty() {
+                return None;
+            }
+            match parse(&self.pending) {
+                Parsed::Event(event, len) => {
+                    self.pending.drain(..len);
+                    return Some(event);
+                },
+                Parsed::Skip(len) => {
+                    self.pending.drain(..len);
+                },
+                Parsed::PasteStart(len) => {
+                    self.pending.drain(..len);
+                    self.pasting = true;
+                },
+                Parsed::Incomplete => return None,
+            }
+        }
+    }
+
+    // Whether bytes are buffered that may still turn out to be part of a sequence.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Called when no more input has arrived for a while. A lone escape byte is
+    // only known to be the escape key once nothing follows it.
+    pub fn flush(&mut self) -> Option<Event> {
+        if let Some(event) = self.next_event() {
+            return Some(event);
+        }
+        if self.pasting || self.pending.is_empty() {
+            return None;
+        }
+        if self.pending[0] == ESC {
+            self.pending.remove(0);
+            Some(Event::Key(KeyCode::Esc.into()))
+        } else {
+            self.pending.clear();
+            None
+        }
+    }
+}
+
+fn parse(bytes: &[u8]) -> Parsed {
+    if bytes[0] != ESC {
+        return match parse_char(bytes) {
+            Some((key, len)) => Parsed::Event(Event::Key(key), len),
+            None if bytes.len() < utf8_len(bytes[0]).unwrap_or(0) => Parsed::Incomplete,
+            None => Parsed::Skip(1),
+        };
+    }
+
+    match bytes.get(1) {
+        None => Parsed::Incomplete,
+        Some(&b'[') => parse_csi(bytes),
+        Some(&b'O') => match bytes.get(2) {
+            None => Parsed::Incomplete,
+            Some(&b) => match ss3_key(b) {
+                Some(code) => Parsed::Event(Event::Key(code.into()), 3),
+                None => Parsed::Skip(3),
+            },
+        },
+        Some(&ESC) => Parsed::Event(Event::Key(KeyCode::Esc.into()), 1),
+        Some(_) => match parse_char(&bytes[1..]) {
+            Some((mut key, len)) => {
+                key.mods = key.mods | Modifiers::ALT;
+                Parsed::Event(Event::Key(key), len + 1)
+            },
+            None if bytes.len() < utf8_len(bytes[1]).unwrap_or(0) + 1 => Parsed::Incomplete,
+            None => Parsed::Skip(2),
+        },
+    }
+}
+
+fn parse_char(bytes: &[u8]) -> Option<(KeyEvent, usize)> {
+    let b = bytes[0];
+    let key = match b {
+        b'\r' => KeyCode::Enter.into(),
+        b'\t' => KeyCode::Tab.into(),
+        0x7F | 0x08 => KeyCode::Backspace.into(),
+        0x00 => KeyEvent::new(KeyCode::Char(' '), Modifiers::CTRL),
+        0x01..=0x1A => KeyEvent::new(KeyCode::Char((b - 1 + b'a') as char), Modifiers::CTRL),
+        0x1C..=0x1F => KeyEvent::new(KeyCode::Char((b - 0x1C + b'4') as char), Modifiers::CTRL),
+        0x20..=0x7E => (b as char).into(),
+        _ => {
+            let len = utf8_len(b)?;
+            let ch = str::from_utf8(bytes.get(..len)?).ok()?.chars().next()?;
+            return Some((ch.into(), len));
+        },
+    };
+    Some((key, 1))
+}
+
+fn utf8_len(lead: u8) -> Option<usize> {
+    match lead {
+        0xC0..=0xDF => Some(2),
+        0xE0..=0xEF => Some(3),
+        0xF0..=0xF7 => Some(4),
+        _ => None,
+    }
+}
+
+fn parse_csi(bytes: &[u8]) -> Parsed {
+    let end = match bytes[2..].iter().position(|b| (0x40..=0x7E).contains(b)) {
+        Some(end) => end + 2,
+        None if bytes.len() < MAX_SEQUENCE => return Parsed::Incomplete,
+        None => return Parsed::Skip(bytes.len()),
+    };
+    let len = end + 1;
+    let params = match str::from_utf8(&bytes[2..end]) {
+        Ok(params) => params,
+        Err(_) => return Parsed::Skip(len),
+    };
+    if let Some(params) = params.strip_prefix('<') {
+        return match parse_sgr_mouse(params, bytes[end]) {
+            Some(mouse) => Parsed::Event(Event::Mouse(mouse), len),
+            None => Parsed::Skip(len),
+        };
+    }
+    if params.starts_with(|c: char| !c.is_ascii_digit() && c != ';') {
+        return Parsed::Skip(len);
+    }
+    let params: Vec<u32> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+    let mods = Modifiers::from_param(params.get(1).cloned().unwrap_or(1));
+
+    let code = match bytes[end] {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'Z' => KeyCode::BackTab,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        b'~' => match params[0] {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F((n - 10) as u8),
+            n @ 17..=21 => KeyCode::F((n - 11) as u8),
+            n @ 23..=24 => KeyCode::F((n - 12) as u8),
+            200 => return Parsed::PasteStart(len),
+            _ => return Parsed::Skip(len),
+        },
+        _ => return Parsed::Skip(len),
+    };
+    Parsed::Event(Event::Key(KeyEvent::new(code, mods)), len)
+}
+
+fn parse_sgr_mouse(params: &str, last: u8) -> Option<MouseEvent> {
+    let mut params = params.split(';').map(|p| p.parse::<usize>());
+    let code = params.next()?.ok()?;
+    let x = params.next()?.ok()?;
+    let y = params.next()?.ok()?;
+
+    let mut mods = Modifiers::NONE;
+    if code & 4 != 0 {
+        mods = mods | Modifiers::SHIFT;
+    }
+    if code & 8 != 0 {
+        mods = mods | Modifiers::ALT;
+    }
+    if code & 16 != 0 {
+        mods = mods | Modifiers::CTRL;
+    }
+
+    let button = match code & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let kind = if code & 64 != 0 {
+        match code & 3 {
+            0 => MouseKind::ScrollUp,
+            1 => MouseKind::ScrollDown,
+            _ => return None,
+        }
+    } else if code & 32 != 0 {
+        button.map_or(MouseKind::Move, MouseKind::Drag)
+    } else {
+        match (last, button) {
+            (b'M', Some(button)) => MouseKind::Press(button),
+            (b'm', Some(button)) => MouseKind::Release(button),
+            _ => return None,
+        }
+    };
+
+    Some(MouseEvent {
+        kind,
+        x: x.saturating_sub(1),
+        y: y.saturating_sub(1),
+        mods,
+    })
+}
+
+fn ss3_key(b: u8) -> Option<KeyCode> {
+    Some(match b {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(bytes: &[u8]) -> Vec<Event> {
+        let mut p = Parser::new();
+        p.feed(bytes);
+        let mut events = Vec::new();
+        while let Some(e) = p.flush() {
+            events.push(e);
+        }
+        events
+    }
+
+    fn key(code: KeyCode, mods: Modifiers) -> Event {
+        Event::Key(KeyEvent::new(code, mods))
+    }
+
+    #[test]
+    fn plain_and_control_keys_work() {
+        assert_eq!(parse_all(b"a\r\t\x7F\x03"), vec![
+            key(KeyCode::Char('a'), Modifiers::NONE),
+            key(KeyCode::Enter, Modifiers::NONE),
+            key(KeyCode::Tab, Modifiers::NONE),
+            key(KeyCode::Backspace, Modifiers::NONE),
+            key(KeyCode::Char('c'), Modifiers::CTRL),
+        ]);
+    }
+
+    #[test]
+    fn utf8_works() {
+        assert_eq!(parse_all("é漢".as_bytes()), vec![
+            key(KeyCode::Char('é'), Modifiers::NONE),
+            key(KeyCode::Char('漢'), Modifiers::NONE),
+        ]);
+
+        let mut p = Parser::new();
+        p.feed(&"漢".as_bytes()[..2]);
+        assert_eq!(p.next_event(), None);
+        p.feed(&"漢".as_bytes()[2..]);
+        assert_eq!(p.next_event(), Some(key(KeyCode::Char('漢'), Modifiers::NONE)));
+    }
+
+    #[test]
+    fn escape_sequences_work() {
+        assert_eq!(parse_all(b"\x1B[A\x1BOB\x1B[1;5C\x1B[5~\x1B[6;2~\x1B[15~\x1BOP\x1B[Z\x1B[3~"), vec![
+            key(KeyCode::Up, Modifiers::NONE),
+            key(KeyCode::Down, Modifiers::NONE),
+            key(KeyCode::Right, Modifiers::CTRL),
+            key(KeyCode::PageUp, Modifiers::NONE),
+            key(KeyCode::PageDown, Modifiers::SHIFT),
+            key(KeyCode::F(5), Modifiers::NONE),
+            key(KeyCode::F(1), Modifiers::NONE),
+            key(KeyCode::BackTab, Modifiers::NONE),
+            key(KeyCode::Delete, Modifiers::NONE),
+        ]);
+    }
+
+    #[test]
+    fn alt_and_escape_work() {
+        let mut p = Parser::new();
+        p.feed(b"\x1Bx");
+        assert_eq!(p.next_event(), Some(key(KeyCode::Char('x'), Modifiers::ALT)));
+        p.feed(b"\x1B");
+        assert_eq!(p.next_event(), None);
+        assert_eq!(p.flush(), Some(key(KeyCode::Esc, Modifiers::NONE)));
+        p.feed(b"\x1B[");
+        assert_eq!(p.next_event(), None);
+        p.feed(b"D");
+        assert_eq!(p.next_event(), Some(key(KeyCode::Left, Modifiers::NONE)));
+    }
+
+    #[test]
+    fn sgr_mouse_works() {
+        let mouse = |kind, x, y, mods| Event::Mouse(MouseEvent { kind, x, y, mods });
+        assert_eq!(parse_all(b"\x1B[<0;3;4M\x1B[<32;4;4M\x1B[<0;4;4m\x1B[<65;1;1M\x1B[<18;10;2M\x1B[<35;7;7M"), vec![
+            mouse(MouseKind::Press(MouseButton::Left), 2, 3, Modifiers::NONE),
+            mouse(MouseKind::Drag(MouseButton::Left), 3, 3, Modifiers::NONE),
+            mouse(MouseKind::Release(MouseButton::Left), 3, 3, Modifiers::NONE),
+            mouse(MouseKind::ScrollDown, 0, 0, Modifiers::NONE),
+            mouse(MouseKind::Press(MouseButton::Right), 9, 1, Modifiers::CTRL),
+            mouse(MouseKind::Move, 6, 6, Modifiers::NONE),
+        ]);
+    }
+
+    #[test]
+    fn bracketed_paste_works() {
+        let mut p = Parser::new();
+        p.feed(b"\x1B[200~hello\x1B[A");
+        assert_eq!(p.flush(), None);
+        p.feed(b"\rworld\x1B[201~q");
+        assert_eq!(p.next_event(), Some(Event::Paste("hello\x1B[A\rworld".to_string())));
+        assert_eq!(p.next_event(), Some(key(KeyCode::Char('q'), Modifiers::NONE)));
+    }
+}
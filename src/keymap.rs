@@ -0,0 +1,142 @@
+use crate::input::{Event, KeyCode, KeyEvent, Modifiers};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveRight,
+    MoveLeft,
+    FocusNext,
+    FocusPrev,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    Activate,
+    Cancel,
+    Quit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<KeyEvent, Action>,
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap { bindings: HashMap::new() }
+    }
+
+    pub fn vi() -> Keymap {
+        let mut keymap = Keymap::common();
+        keymap.bind('k', Action::MoveUp);
+        keymap.bind('j', Action::MoveDown);
+        keymap.bind('l', Action::MoveRight);
+        keymap.bind('h', Action::MoveLeft);
+        keymap.bind(ctrl('y'), Action::ScrollUp);
+        keymap.bind(ctrl('e'), Action::ScrollDown);
+        keymap.bind(ctrl('b'), Action::PageUp);
+        keymap.bind(ctrl('f'), Action::PageDown);
+        keymap.bind('q', Action::Quit);
+        keymap
+    }
+
+    pub fn arrows() -> Keymap {
+        let mut keymap = Keymap::common();
+        keymap.bind(KeyCode::Up, Action::MoveUp);
+        keymap.bind(KeyCode::Down, Action::MoveDown);
+        keymap.bind(KeyCode::Right, Action::MoveRight);
+        keymap.bind(KeyCode::Left, Action::MoveLeft);
+        keymap.bind(KeyEvent::new(KeyCode::Up, Modifiers::CTRL), Action::ScrollUp);
+        keymap.bind(KeyEvent::new(KeyCode::Down, Modifiers::CTRL), Action::ScrollDown);
+        keymap
+    }
+
+    pub fn wasd() -> Keymap {
+        let mut keymap = Keymap::common();
+        keymap.bind('w', Action::MoveUp);
+        keymap.bind('s', Action::MoveDown);
+        keymap.bind('d', Action::MoveRight);
+        keymap.bind('a', Action::MoveLeft);
+        keymap.bind('W', Action::ScrollUp);
+        keymap.bind('S', Action::ScrollDown);
+        keymap
+    }
+
+    // Bindings shared by every preset.
+    fn common() -> Keymap {
+        let mut keymap = Keymap::new();
+        keymap.bind(KeyCode::PageUp, Action::PageUp);
+        keymap.bind(KeyCode::PageDown, Action::PageDown);
+        keymap.bind(KeyCode::Tab, Action::FocusNext);
+        keymap.bind(KeyCode::BackTab, Action::FocusPrev);
+        keymap.bind(KeyCode::Enter, Action::Activate);
+        keymap.bind(KeyCode::Esc, Action::Cancel);
+        keymap.bind(ctrl('c'), Action::Quit);
+        keymap
+    }
+
+    // Returns the action the key was previously bound to, if any.
+    pub fn bind<K: Into<KeyEvent>>(&mut self, key: K, action: Action) -> Option<Action> {
+        self.bindings.insert(key.into(), action)
+    }
+
+    pub fn unbind<K: Into<KeyEvent>>(&mut self, key: K) -> Option<Action> {
+        self.bindings.remove(&key.into())
+    }
+
+    // Adds all of another keymap's bindings, which win over existing ones.
+    pub fn merge(mut self, other: Keymap) -> Keymap {
+        self.bindings.extend(other.bindings);
+        self
+    }
+
+    pub fn action(&self, event: &Event) -> Option<Action> {
+        match *event {
+            Event::Key(ref key) => self.bindings.get(key).cloned(),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::vi().merge(Keymap::arrows())
+    }
+}
+
+fn ctrl(ch: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(ch), Modifiers::CTRL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key<K: Into<KeyEvent>>(key: K) -> Event {
+        Event::Key(key.into())
+    }
+
+    #[test]
+    fn presets_work() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(&key('j')), Some(Action::MoveDown));
+        assert_eq!(keymap.action(&key(KeyCode::Down)), Some(Action::MoveDown));
+        assert_eq!(keymap.action(&key(KeyCode::Enter)), Some(Action::Activate));
+        assert_eq!(keymap.action(&key('w')), None);
+        assert_eq!(Keymap::wasd().action(&key('w')), Some(Action::MoveUp));
+        assert_eq!(Keymap::new().action(&key(KeyCode::Esc)), None);
+        assert_eq!(Keymap::arrows().action(&key(ctrl('c'))), Some(Action::Quit));
+    }
+
+    #[test]
+    fn rebinding_works() {
+        let mut keymap = Keymap::vi();
+        assert_eq!(keymap.bind('j', Action::PageDown), Some(Action::MoveDown));
+        assert_eq!(keymap.action(&key('j')), Some(Action::PageDown));
+        assert_eq!(keymap.unbind('j'), Some(Action::PageDown));
+        assert_eq!(keymap.action(&key('j')), None);
+    }
+}
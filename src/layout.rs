@@ -0,0 +1,325 @@
+use crate::ElemHandle;
+
+// How big an element would like to be. Layouts try to respect these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SizeHint {
+    pub min: (usize, usize),
+    pub preferred: (usize, usize),
+    pub max: (usize, usize),
+}
+
+impl SizeHint {
+    pub fn fixed(width: usize, height: usize) -> SizeHint {
+        SizeHint {
+            min: (width, height),
+            preferred: (width, height),
+            max: (width, height),
+        }
+    }
+
+    pub fn flexible(width: usize, height: usize) -> SizeHint {
+        SizeHint {
+            preferred: (width, height),
+            ..SizeHint::default()
+        }
+    }
+}
+
+impl Default for SizeHint {
+    fn default() -> SizeHint {
+        SizeHint {
+            min: (0, 0),
+            preferred: (0, 0),
+            max: (usize::MAX, usize::MAX),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// How much of a row or column an item takes up along its direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Constraint {
+    Fixed(usize),
+    Percent(usize),
+    // A share of whatever space the other items leave over.
+    Flex(usize),
+    // The item's preferred size.
+    Auto,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Item {
+    Elem(ElemHandle),
+    Nested(Layout),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Layout {
+    direction: Direction,
+    items: Vec<(Item, Constraint)>,
+    padding: usize,
+    gap: usize,
+}
+
+impl Layout {
+    pub fn new(direction: Direction) -> Layout {
+        Layout {
+            direction,
+            items: Vec::new(),
+            padding: 0,
+            gap: 0,
+        }
+    }
+
+    pub fn row() -> Layout {
+        Layout::new(Direction::Row)
+    }
+
+    pub fn column() -> Layout {
+        Layout::new(Direction::Column)
+    }
+
+    // Space left empty around all the items.
+    pub fn padding(mut self, padding: usize) -> Layout {
+        self.padding = padding;
+        self
+    }
+
+    // Space left empty between each item.
+    pub fn gap(mut self, gap: usize) -> Layout {
+        self.gap = gap;
+        self
+    }
+
+    pub fn elem(mut self, elem: ElemHandle, constraint: Constraint) -> Layout {
+        self.items.push((Item::Elem(elem), constraint));
+        self
+    }
+
+    pub fn nested(mut self, layout: Layout, constraint: Constraint) -> Layout {
+        self.items.push((Item::Nested(layout), constraint));
+        self
+    }
+
+    // Works out where each element goes within `area`.
+    pub fn compute<F>(&self, area: Rect, hint: &F) -> Vec<(ElemHandle, Rect)>
+        where F: Fn(ElemHandle) -> SizeHint
+    {
+        let mut rects = Vec::new();
+        self.compute_into(area, hint, &mut rects);
+        rects
+    }
+
+    fn compute_into<F>(&self, area: Rect, hint: &F, rects: &mut Vec<(ElemHandle, Rect)>)
+        where F: Fn(ElemHandle) -> SizeHint
+    {
+        if self.items.is_empty() {
+            return;
+        }
+        let inner = Rect {
+            x: area.x + self.padding,
+            y: area.y + self.padding,
+            width: area.width.saturating_sub(self.padding * 2),
+            height: area.height.saturating_sub(self.padding * 2),
+        };
+        let (main, cross) = self.split(inner.width, inner.height);
+        let available = main.saturating_sub(self.gap * (self.items.len() - 1));
+
+        let hints: Vec<SizeHint> = self.items.iter().map(|(item, _)| item.hint(hint)).collect();
+        let mut sizes: Vec<usize> = Vec::with_capacity(self.items.len());
+        let mut total_flex = 0;
+        for (&(_, constraint), item_hint) in self.items.iter().zip(&hints) {
+            let (min, max) = (self.main(item_hint.min), self.main(item_hint.max));
+            sizes.push(match constraint {
+                Constraint::Fixed(size) => size,
+                Constraint::Percent(percent) => available * percent / 100,
+                Constraint::Auto => self.main(item_hint.preferred).max(min).min(max),
+                Constraint::Flex(weight) => {
+                    total_flex += weight;
+                    0
+                },
+            });
+        }
+
+        let mut left_over = available.saturating_sub(sizes.iter().sum());
+        let to_share = left_over;
+        for (i, &(_, constraint)) in self.items.iter().enumerate() {
+            if let Constraint::Flex(weight) = constraint {
+                let share = (to_share * weight).checked_div(total_flex).unwrap_or(0);
+                sizes[i] = share.max(self.main(hints[i].min)).min(self.main(hints[i].max));
+                left_over = left_over.saturating_sub(sizes[i]);
+            }
+        }
+        // Hand out what integer division left behind one column at a time.
+        for (i, &(_, constraint)) in self.items.iter().enumerate() {
+            if left_over == 0 {
+                break;
+            }
+            if let Constraint::Flex(weight) = constraint {
+                if weight > 0 && sizes[i] < self.main(hints[i].max) {
+                    sizes[i] += 1;
+                    left_over -= 1;
+                }
+            }
+        }
+
+        let mut offset = 0;
+        for (((item, _), item_hint), size) in self.items.iter().zip(&hints).zip(sizes) {
+            let size = size.min(main.saturating_sub(offset));
+            let item_cross = cross.min(self.cross(item_hint.max));
+            let (x, y) = self.join(offset, 0);
+            let (width, height) = self.join(size, item_cross);
+            let rect = Rect { x: inner.x + x, y: inner.y + y, width, height };
+            match *item {
+                Item::Elem(elem) => rects.push((elem, rect)),
+                Item::Nested(ref layout) => layout.compute_into(rect, hint, rects),
+            }
+            offset += size + self.gap;
+        }
+    }
+
+    // The size hint of the whole layout, built from those of its items.
+    fn hint<F>(&self, hint: &F) -> SizeHint
+        where F: Fn(ElemHandle) -> SizeHint
+    {
+        let hints: Vec<SizeHint> = self.items.iter().map(|(item, _)| item.hint(hint)).collect();
+        let extra = self.padding * 2;
+        let gaps = self.gap * self.items.len().saturating_sub(1);
+        let combine = |get: &dyn Fn(&SizeHint) -> (usize, usize)| {
+            let main: usize = hints.iter().map(|h| self.main(get(h))).sum();
+            let cross = hints.iter().map(|h| self.cross(get(h))).max().unwrap_or(0);
+            let (width, height) = self.join(main + gaps + extra, cross + extra);
+            (width, height)
+        };
+        SizeHint {
+            min: combine(&|h| h.min),
+            preferred: combine(&|h| h.preferred),
+            max: (usize::MAX, usize::MAX),
+        }
+    }
+
+    fn split(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.direction {
+            Direction::Row => (width, height),
+            Direction::Column => (height, width),
+        }
+    }
+
+    fn join(&self, main: usize, cross: usize) -> (usize, usize) {
+        self.split(main, cross)
+    }
+
+    fn main(&self, size: (usize, usize)) -> usize {
+        self.split(size.0, size.1).0
+    }
+
+    fn cross(&self, size: (usize, usize)) -> usize {
+        self.split(size.0, size.1).1
+    }
+}
+
+impl Item {
+    fn hint<F>(&self, hint: &F) -> SizeHint
+        where F: Fn(ElemHandle) -> SizeHint
+    {
+        match *self {
+            Item::Elem(elem) => hint(elem),
+            Item::Nested(ref layout) => layout.hint(hint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(width: usize, height: usize) -> Rect {
+        Rect { x: 0, y: 0, width, height }
+    }
+
+    fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    fn handle(index: usize) -> ElemHandle {
+        ElemHandle { index, generation: 0 }
+    }
+
+    #[test]
+    fn rows_work() {
+        let layout = Layout::row().padding(1).gap(1)
+            .elem(handle(0), Constraint::Fixed(3))
+            .elem(handle(1), Constraint::Flex(1))
+            .elem(handle(2), Constraint::Percent(50))
+            .elem(handle(3), Constraint::Auto);
+        let hint = |e: ElemHandle| match e.index {
+            3 => SizeHint::fixed(2, 1),
+            _ => SizeHint::default(),
+        };
+        assert_eq!(layout.compute(area(24, 5), &hint), vec![
+            (handle(0), rect(1, 1, 3, 3)),
+            (handle(1), rect(5, 1, 5, 3)),
+            (handle(2), rect(11, 1, 9, 3)),
+            (handle(3), rect(21, 1, 2, 1)),
+        ]);
+    }
+
+    #[test]
+    fn flex_weights_and_limits_work() {
+        let layout = Layout::column()
+            .elem(handle(0), Constraint::Flex(1))
+            .elem(handle(1), Constraint::Flex(2))
+            .elem(handle(2), Constraint::Flex(1));
+        let hint = |e: ElemHandle| match e.index {
+            2 => SizeHint { max: (4, 2), ..SizeHint::default() },
+            _ => SizeHint::default(),
+        };
+        assert_eq!(layout.compute(area(6, 11), &hint), vec![
+            (handle(0), rect(0, 0, 6, 3)),
+            (handle(1), rect(0, 3, 6, 6)),
+            (handle(2), rect(0, 9, 4, 2)),
+        ]);
+    }
+
+    #[test]
+    fn nesting_works() {
+        let layout = Layout::column()
+            .elem(handle(0), Constraint::Fixed(1))
+            .nested(Layout::row().gap(1)
+                        .elem(handle(1), Constraint::Auto)
+                        .elem(handle(2), Constraint::Flex(1)),
+                    Constraint::Flex(1))
+            .elem(handle(3), Constraint::Auto);
+        let hint = |e: ElemHandle| SizeHint::flexible(e.index * 2, 1);
+        assert_eq!(layout.compute(area(10, 6), &hint), vec![
+            (handle(0), rect(0, 0, 10, 1)),
+            (handle(1), rect(0, 1, 2, 4)),
+            (handle(2), rect(3, 1, 7, 4)),
+            (handle(3), rect(0, 5, 10, 1)),
+        ]);
+    }
+
+    #[test]
+    fn overflow_is_clipped() {
+        let layout = Layout::row()
+            .elem(handle(0), Constraint::Fixed(4))
+            .elem(handle(1), Constraint::Fixed(4));
+        assert_eq!(layout.compute(area(6, 1), &|_| SizeHint::default()), vec![
+            (handle(0), rect(0, 0, 4, 1)),
+            (handle(1), rect(4, 0, 2, 1)),
+        ]);
+    }
+}
@@ -1,47 +1,134 @@
+pub mod app;
 pub mod canvas;
-use canvas::Canvas;
+use crate::canvas::{Canvas, CanvasView};
+pub mod harness;
+pub mod input;
+use crate::input::{Event, KeyEvent, MouseEvent, MouseKind};
+pub mod keymap;
+use crate::keymap::{Action, Keymap};
+pub mod layout;
+use crate::layout::{Layout, Rect, SizeHint};
+pub mod render;
+pub mod snapshot;
+#[cfg(unix)]
+pub mod term;
 pub mod util;
+pub mod vt;
 
-use std::borrow::BorrowMut;
+use std::any::{Any, TypeId};
+use std::cell::Cell;
 use std::error::Error;
-use std::fmt::{self, Display, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::mem;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-pub enum Response<'a> {
+// Responses holding messages never compare equal, since message payloads cannot
+// be compared. Use `Message::downcast_ref` to look inside one instead.
+#[derive(Debug, PartialEq)]
+pub enum Response {
     Nothing,
     Contained,
     MoveUp,
     MoveDown,
     MoveRight,
     MoveLeft,
-    Alert(&'a [ElemHandle]),
+    FocusNext,
+    FocusPrev,
+    Alert(Vec<ElemHandle>),
+    Message(Message),
 }
 
-pub const UP: char = 'k';
-pub const DOWN: char = 'j';
-pub const RIGHT: char = 'l';
-pub const LEFT: char = 'h';
+// Something an element has to say to the application, such as that a form was
+// submitted. It is passed up through each grid on the way to the root.
+pub struct Message {
+    payload: Box<dyn Any>,
+    path: Vec<ElemHandle>,
+}
+
+impl Message {
+    pub fn new<T: Any>(payload: T) -> Message {
+        Message {
+            payload: Box::new(payload),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.payload.is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.payload.downcast_ref()
+    }
+
+    // Gives back the message unchanged if the payload is not a T.
+    pub fn downcast<T: Any>(self) -> Result<T, Message> {
+        let Message { payload, path } = self;
+        payload.downcast().map(|payload| *payload).map_err(|payload| Message { payload, path })
+    }
+
+    // The handles of the grids' children that the message passed through, from
+    // the outermost grid down to the sender.
+    pub fn path(&self) -> &[ElemHandle] {
+        &self.path
+    }
+}
+
+impl Debug for Message {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Message").field("path", &self.path).finish()
+    }
+}
+
+// Payloads cannot be compared, so messages are never equal, not even to themselves.
+impl PartialEq for Message {
+    fn eq(&self, _other: &Message) -> bool {
+        false
+    }
+}
 
 pub trait Element<'a> {
-    fn draw(&self, canvas: &mut Canvas, x: usize, y: usize, selected: bool);
+    fn draw(&self, canvas: &mut CanvasView, selected: bool);
+
+    fn size_hint(&self) -> SizeHint { SizeHint::default() }
 
     fn advance(&mut self) { }
 
-    fn draw_advance(&mut self, canvas: &mut Canvas, x: usize, y: usize, selected: bool) {
-        self.draw(canvas, x, y, selected);
+    // Called when the space available to the element changes.
+    fn resize(&mut self, _width: usize, _height: usize) { }
+
+    fn draw_advance(&mut self, canvas: &mut CanvasView, selected: bool) {
+        self.draw(canvas, selected);
         self.advance()
     }
 
-    fn respond<'b>(&'b mut self, input: char) -> Response<'b> {
-        match input {
-            UP    => Response::MoveUp,
-            DOWN  => Response::MoveDown,
-            RIGHT => Response::MoveRight,
-            LEFT  => Response::MoveLeft,
-            _     => Response::Nothing,
+    fn respond_event(&mut self, event: &Event, keymap: &Keymap) -> Response {
+        match *event {
+            Event::Mouse(ref mouse) => self.respond_mouse(mouse),
+            _ => match keymap.action(event) {
+                Some(action) => self.respond_action(action),
+                None => Response::Nothing,
+            },
+        }
+    }
+
+    fn respond_action(&mut self, action: Action) -> Response {
+        match action {
+            Action::MoveUp    => Response::MoveUp,
+            Action::MoveDown  => Response::MoveDown,
+            Action::MoveRight => Response::MoveRight,
+            Action::MoveLeft  => Response::MoveLeft,
+            Action::FocusNext => Response::FocusNext,
+            Action::FocusPrev => Response::FocusPrev,
+            _                 => Response::Nothing,
         }
     }
 
+    fn respond_mouse(&mut self, _mouse: &MouseEvent) -> Response {
+        Response::Nothing
+    }
+
     fn enter_top(&mut self) { }
 
     fn enter_bottom(&mut self) { }
@@ -50,131 +137,583 @@ pub trait Element<'a> {
 
     fn enter_left(&mut self) { }
 
+    // Called when a mouse event at a position is about to focus the element.
+    fn enter_at(&mut self, _x: usize, _y: usize) { }
+
+    // Whether the element can take focus when focus moves in tab order.
+    fn focusable(&self) -> bool { true }
+
+    // Called when focus moves onto the element in tab order, going forwards.
+    fn enter_first(&mut self) { }
+
+    // Called when focus moves onto the element in tab order, going backwards.
+    fn enter_last(&mut self) { }
+
+    // Called when the element becomes the focused one in its grid, after it has
+    // been entered.
+    fn focus_gained(&mut self) { }
+
+    fn focus_lost(&mut self) { }
+
+    // Adds the handles along the path to whatever is focused inside the element.
+    fn extend_focus_path(&self, _path: &mut Vec<ElemHandle>) { }
+
     fn alert(&mut self) { }
 }
 
-pub struct Grid<'a> {
-    elems: Vec<ElemHolder<'a>>,
+// A grid of elements of type E. By default, any elements can go in a grid, but a
+// SendGrid only takes elements that can be sent to other threads, so that it can
+// be too.
+pub struct Grid<'a, E: ?Sized + Element<'a> + 'a = dyn Element<'a> + 'a> {
+    elems: Vec<Slot<E>>,
+    // Indices of live elements in the order they are drawn.
+    stack: Vec<usize>,
+    // Indices of empty slots that new elements can reuse.
+    free: Vec<usize>,
     focus: usize,
+    layout: Option<Layout>,
+    size: (usize, usize),
+    // The size the grid was last drawn or resized at. Mouse events and spatial
+    // navigation go by this, so they agree with what is on screen even if the
+    // grid is never resized.
+    area: Cell<(usize, usize)>,
+    spatial: bool,
+    order: Option<Vec<usize>>,
+    wrap: bool,
+    _a: PhantomData<&'a ()>,
 }
 
+pub type SendGrid<'a> = Grid<'a, dyn Element<'a> + Send + 'a>;
+
 const TL_IDX: usize = 0; // Index of top left element
 const BR_IDX: usize = 1; // Index of bottom right element
 
 impl<'a> Grid<'a> {
-    pub fn with_capacity(tl: Box<Element<'a> + 'a>, tl_x: usize, tl_y: usize,
-                         br: Box<Element<'a> + 'a>, br_x: usize, br_y: usize,
+    pub fn with_capacity(tl: Box<dyn Element<'a> + 'a>, tl_x: usize, tl_y: usize,
+                         br: Box<dyn Element<'a> + 'a>, br_x: usize, br_y: usize,
                          cap: usize)
     -> Grid<'a> {
+        let mut grid = Grid::new(tl, tl_x, tl_y, br, br_x, br_y);
+        grid.elems.reserve(cap);
+        grid
+    }
+
+    // Like `new`, but the top left and bottom right elements can later be gotten
+    // back as their own types with `get`.
+    pub fn new_typed<T, U>(tl: T, tl_x: usize, tl_y: usize, br: U, br_x: usize, br_y: usize) -> Grid<'a>
+        where T: Element<'a> + 'static,
+              U: Element<'a> + 'static
+    {
+        Grid::from_corners(ElemHolder::new(Box::new(tl), Some(TypeId::of::<T>()), tl_x, tl_y),
+                           ElemHolder::new(Box::new(br), Some(TypeId::of::<U>()), br_x, br_y))
+    }
+
+    // Adds an element that can later be gotten back as its own type with `get`.
+    pub fn add<T: Element<'a> + 'static>(&mut self, elem: T, x: usize, y: usize) -> ElemHandle {
+        self.insert(Box::new(elem), Some(TypeId::of::<T>()), x, y)
+    }
+
+    // Like `replace`, but the new element can later be gotten back as its own type.
+    pub fn replace_typed<T: Element<'a> + 'static>(&mut self, elem: ElemHandle, new: T) -> Result<Box<dyn Element<'a> + 'a>, InvalidHandle> {
+        self.replace_with_type(elem, Box::new(new), Some(TypeId::of::<T>()))
+    }
+}
+
+impl<'a> SendGrid<'a> {
+    pub fn add<T: Element<'a> + Send + 'static>(&mut self, elem: T, x: usize, y: usize) -> ElemHandle {
+        self.insert(Box::new(elem), Some(TypeId::of::<T>()), x, y)
+    }
+
+    pub fn replace_typed<T: Element<'a> + Send + 'static>(&mut self, elem: ElemHandle, new: T) -> Result<Box<dyn Element<'a> + Send + 'a>, InvalidHandle> {
+        self.replace_with_type(elem, Box::new(new), Some(TypeId::of::<T>()))
+    }
+}
+
+impl<'a, E: ?Sized + Element<'a> + 'a> Grid<'a, E> {
+    pub fn new(tl: Box<E>, tl_x: usize, tl_y: usize, br: Box<E>, br_x: usize, br_y: usize) -> Grid<'a, E> {
+        Grid::from_corners(ElemHolder::new(tl, None, tl_x, tl_y), ElemHolder::new(br, None, br_x, br_y))
+    }
+
+    fn from_corners(tl: ElemHolder<E>, br: ElemHolder<E>) -> Grid<'a, E> {
         Grid {
-            elems: {
-                let mut elems = Vec::with_capacity(cap + 2);
-                elems.push(ElemHolder::new(tl, tl_x, tl_y));
-                elems.push(ElemHolder::new(br, br_x, br_y));
-                elems
-            },
+            elems: vec![Slot::new(tl), Slot::new(br)],
+            stack: vec![TL_IDX, BR_IDX],
+            free: Vec::new(),
             focus: TL_IDX,
+            layout: None,
+            size: (0, 0),
+            area: Cell::new((0, 0)),
+            spatial: false,
+            order: None,
+            wrap: false,
+            _a: PhantomData,
         }
     }
 
+    // Positions and sizes the elements named in the layout whenever the grid is
+    // resized. Other elements keep their positions.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = Some(layout);
+        let (width, height) = self.size;
+        self.resize(width, height)
+    }
+
+    // When on, moving from an element with no connection in some direction goes to
+    // the nearest element in that direction instead of leaving the grid.
+    pub fn set_spatial_navigation(&mut self, on: bool) {
+        self.spatial = on;
+    }
+
+    // Sets the order in which FocusNext and FocusPrev visit elements. Elements
+    // left out are skipped. By default, elements are visited in the order added.
+    pub fn set_focus_order(&mut self, order: Vec<ElemHandle>) -> Result<(), InvalidHandle> {
+        let order = order.into_iter().map(|elem| self.holder(elem).map(|_| elem.index)).collect::<Result<_, _>>()?;
+        self.order = Some(order);
+        Ok(())
+    }
+
+    pub fn set_focusable(&mut self, elem: ElemHandle, focusable: bool) -> Result<(), InvalidHandle> {
+        self.holder_mut(elem)?.focusable = focusable;
+        Ok(())
+    }
+
+    // When on, focus moving past the last element in tab order comes back around
+    // to the first instead of leaving the grid, and the other way around.
+    pub fn set_focus_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    // Responds to a plain key as if it were pressed with the default keymap.
+    // Elements take input through `respond_event` and `respond_action`.
+    pub fn respond(&mut self, input: char) -> Response {
+        Element::respond_event(self, &Event::Key(KeyEvent::from(input)), &Keymap::default())
+    }
+
     pub fn draw(&self, canvas: &mut Canvas) {
-        Element::draw(self, canvas, 0, 0, true);
+        Element::draw(self, &mut canvas.view(), true);
     }
 
     pub fn draw_advance(&mut self, canvas: &mut Canvas) {
-        Element::draw_advance(self, canvas, 0, 0, true);
+        Element::draw_advance(self, &mut canvas.view(), true);
     }
 
-    fn focus(&self) -> &ElemHolder<'a> {
-        unsafe {
-            self.elems.get_unchecked(self.focus)
-        }
+    fn focus(&self) -> &ElemHolder<E> {
+        self.at(self.focus)
     }
 
-    fn focus_mut(&mut self) -> &mut ElemHolder<'a> {
-        unsafe {
-            self.elems.get_unchecked_mut(self.focus)
+    fn focus_mut(&mut self) -> &mut ElemHolder<E> {
+        let focus = self.focus;
+        self.at_mut(focus)
+    }
+
+    // The element at an index known to be in use.
+    fn at(&self, i: usize) -> &ElemHolder<E> {
+        self.elems[i].holder.as_ref().unwrap()
+    }
+
+    fn at_mut(&mut self, i: usize) -> &mut ElemHolder<E> {
+        self.elems[i].holder.as_mut().unwrap()
+    }
+
+    fn holder(&self, elem: ElemHandle) -> Result<&ElemHolder<E>, InvalidHandle> {
+        self.elems.get(elem.index).and_then(|slot| slot.get(elem)).ok_or(InvalidHandle(elem))
+    }
+
+    fn holder_mut(&mut self, elem: ElemHandle) -> Result<&mut ElemHolder<E>, InvalidHandle> {
+        self.elems.get_mut(elem.index).and_then(|slot| slot.get_mut(elem)).ok_or(InvalidHandle(elem))
+    }
+
+    fn handle(&self, i: usize) -> ElemHandle {
+        ElemHandle {
+            index: i,
+            generation: self.elems[i].generation,
         }
     }
 
     pub fn top_left(&self) -> ElemHandle {
-        ElemHandle(TL_IDX)
+        self.handle(TL_IDX)
     }
 
     pub fn bottom_right(&self) -> ElemHandle {
-        ElemHandle(BR_IDX)
+        self.handle(BR_IDX)
     }
 
-    pub fn add_elem(&mut self, elem: Box<Element<'a> + 'a>, x: usize, y: usize) -> ElemHandle {
-        self.elems.push(ElemHolder::new(elem, x, y));
-        ElemHandle(self.elems.len() - 1)
+    // Adds an element of unknown type, which `get` cannot give back. Use `add`
+    // for elements that will be looked up later.
+    pub fn add_elem(&mut self, elem: Box<E>, x: usize, y: usize) -> ElemHandle {
+        self.insert(elem, None, x, y)
     }
 
-    pub fn connect_up_down(&mut self, up: ElemHandle, down: ElemHandle) -> Result<(), InvalidHandle> {
-        let upper: *mut ElemHolder<'a> = self.elems.get(up.0).ok_or(InvalidHandle(up))? as *const _ as *mut _;
-        let lower: *mut ElemHolder<'a> = self.elems.get(down.0).ok_or(InvalidHandle(down))? as *const _ as *mut _;
-        unsafe {
-            (&mut *upper).down = down.0 as isize;
-            (&mut *lower).up = up.0 as isize;
+    fn insert(&mut self, elem: Box<E>, type_id: Option<TypeId>, x: usize, y: usize) -> ElemHandle {
+        let mut holder = ElemHolder::new(elem, type_id, x, y);
+        let (grid_width, grid_height) = self.size;
+        holder.resize(grid_width, grid_height);
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.elems[i].holder = Some(holder);
+                i
+            },
+            None => {
+                self.elems.push(Slot::new(holder));
+                self.elems.len() - 1
+            },
+        };
+        self.stack.push(i);
+        self.handle(i)
+    }
+
+    // Returns None if the handle is invalid or the element was not put in the grid
+    // as a T. Only elements put in by value, through `add`, `new_typed` or
+    // `replace_typed`, have a known type. Boxed ones, as taken by `new`,
+    // `with_capacity`, `add_elem` and `replace`, are never given back.
+    pub fn get<T: Element<'a> + 'static>(&self, elem: ElemHandle) -> Option<&T> {
+        let holder = self.holder(elem).ok()?;
+        // The element was boxed from a T, so the cast only drops the vtable.
+        if holder.type_id == Some(TypeId::of::<T>()) {
+            Some(unsafe { &*(&*holder.elem as *const E as *const T) })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut<T: Element<'a> + 'static>(&mut self, elem: ElemHandle) -> Option<&mut T> {
+        let holder = self.holder_mut(elem).ok()?;
+        if holder.type_id == Some(TypeId::of::<T>()) {
+            Some(unsafe { &mut *(&mut *holder.elem as *mut E as *mut T) })
+        } else {
+            None
+        }
+    }
+
+    // Yields each element with its handle and position, in the order they are drawn.
+    pub fn children<'b>(&'b self) -> impl Iterator<Item = (ElemHandle, usize, usize, &'b E)> + use<'a, 'b, E> {
+        self.stack.iter().map(move |&i| {
+            let holder = self.at(i);
+            (self.handle(i), holder.x, holder.y, &*holder.elem)
+        })
+    }
+
+    // Takes an element out of the grid, disconnecting it from its neighbors. The
+    // top left and bottom right elements cannot be removed, only replaced.
+    pub fn remove(&mut self, elem: ElemHandle) -> Result<Box<E>, InvalidHandle> {
+        if elem.index == TL_IDX || elem.index == BR_IDX {
+            return Err(InvalidHandle(elem));
+        }
+        self.holder(elem)?;
+        if self.focus == elem.index {
+            self.change_focus(TL_IDX, |_| ());
+        }
+        let slot = &mut self.elems[elem.index];
+        let holder = slot.holder.take().unwrap();
+        slot.generation += 1;
+        self.free.push(elem.index);
+
+        let removed = elem.index as isize;
+        for holder in self.elems.iter_mut().filter_map(|slot| slot.holder.as_mut()) {
+            for link in &mut [&mut holder.up, &mut holder.down, &mut holder.right, &mut holder.left] {
+                if **link == removed {
+                    **link = -1;
+                }
+            }
+        }
+        self.stack.retain(|&i| i != elem.index);
+        if let Some(ref mut order) = self.order {
+            order.retain(|&i| i != elem.index);
         }
+        Ok(holder.elem)
+    }
+
+    // Swaps in a new element in place of an old one, keeping its position and
+    // connections. The old element is returned.
+    pub fn replace(&mut self, elem: ElemHandle, new: Box<E>) -> Result<Box<E>, InvalidHandle> {
+        self.replace_with_type(elem, new, None)
+    }
+
+    fn replace_with_type(&mut self, elem: ElemHandle, new: Box<E>, type_id: Option<TypeId>) -> Result<Box<E>, InvalidHandle> {
+        let (grid_width, grid_height) = self.size;
+        let focused = elem.index == self.focus;
+        let holder = self.holder_mut(elem)?;
+        if focused {
+            holder.elem.focus_lost();
+        }
+        let old = mem::replace(&mut holder.elem, new);
+        holder.type_id = type_id;
+        holder.resize(grid_width, grid_height);
+        // A new focused element is entered before it hears about it, as when
+        // focus moves onto it.
+        if focused {
+            holder.elem.enter_first();
+            holder.elem.focus_gained();
+        }
+        Ok(old)
+    }
+
+    pub fn move_elem(&mut self, elem: ElemHandle, x: usize, y: usize) -> Result<(), InvalidHandle> {
+        let (grid_width, grid_height) = self.size;
+        let holder = self.holder_mut(elem)?;
+        holder.x = x;
+        holder.y = y;
+        holder.resize(grid_width, grid_height);
+        Ok(())
+    }
+
+    // Draws the element over all the others. It also comes last in the default tab order.
+    pub fn raise(&mut self, elem: ElemHandle) -> Result<(), InvalidHandle> {
+        self.holder(elem)?;
+        self.stack.retain(|&i| i != elem.index);
+        self.stack.push(elem.index);
+        Ok(())
+    }
+
+    // Draws the element under all the others. It also comes first in the default tab order.
+    pub fn lower(&mut self, elem: ElemHandle) -> Result<(), InvalidHandle> {
+        self.holder(elem)?;
+        self.stack.retain(|&i| i != elem.index);
+        self.stack.insert(0, elem.index);
+        Ok(())
+    }
 
+    pub fn connect_up_down(&mut self, up: ElemHandle, down: ElemHandle) -> Result<(), InvalidHandle> {
+        self.holder(up)?;
+        self.holder(down)?;
+        self.at_mut(up.index).down = down.index as isize;
+        self.at_mut(down.index).up = up.index as isize;
         Ok(())
     }
 
     pub fn connect_left_right(&mut self, left: ElemHandle, right: ElemHandle) -> Result<(), InvalidHandle> {
-        let lefter: *mut ElemHolder<'a> = self.elems.get(left.0).ok_or(InvalidHandle(left))? as *const _ as *mut _;
-        let righter: *mut ElemHolder<'a> = self.elems.get(right.0).ok_or(InvalidHandle(right))? as *const _ as *mut _;
-        unsafe {
-            (&mut *lefter).right = right.0 as isize;
-            (&mut *righter).left = left.0 as isize;
+        self.holder(left)?;
+        self.holder(right)?;
+        self.at_mut(left.index).right = right.index as isize;
+        self.at_mut(right.index).left = left.index as isize;
+        Ok(())
+    }
+
+    fn move_up(&mut self) -> Response {
+        match self.neighbor(self.focus().up, Side::Up) {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_top());
+                Response::Contained
+            },
+            None => Response::MoveUp,
         }
+    }
 
-        Ok(())
+    fn move_down(&mut self) -> Response {
+        match self.neighbor(self.focus().down, Side::Down) {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_bottom());
+                Response::Contained
+            },
+            None => Response::MoveDown,
+        }
     }
 
-    fn move_up<'b>(&'b mut self) -> Response<'b> {
-        if self.focus().up >= 0 {
-            self.focus = self.focus().up as usize;
-            self.focus_mut().elem.enter_top();
-            Response::Contained
-        } else {
-            Response::MoveUp
+    fn move_right(&mut self) -> Response {
+        match self.neighbor(self.focus().right, Side::Right) {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_right());
+                Response::Contained
+            },
+            None => Response::MoveRight,
         }
     }
 
-    fn move_down<'b>(&'b mut self) -> Response<'b> {
-        if self.focus().down >= 0 {
-            self.focus = self.focus().down as usize;
-            self.focus_mut().elem.enter_bottom();
-            Response::Contained
-        } else {
-            Response::MoveDown
+    fn move_left(&mut self) -> Response {
+        match self.neighbor(self.focus().left, Side::Left) {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_left());
+                Response::Contained
+            },
+            None => Response::MoveLeft,
         }
     }
 
-    fn move_right<'b>(&'b mut self) -> Response<'b> {
-        if self.focus().right >= 0 {
-            self.focus = self.focus().right as usize;
-            self.focus_mut().elem.enter_right();
-            Response::Contained
-        } else {
-            Response::MoveRight
+    fn focus_next(&mut self) -> Response {
+        let order = self.focus_order();
+        let start = order.iter().position(|&i| i == self.focus).map_or(0, |pos| pos + 1);
+        let mut candidates = order[start..].iter();
+        let next = match candidates.find(|&&i| self.at(i).is_focusable()) {
+            Some(&i) => Some(i),
+            None if self.wrap => order.iter().cloned().find(|&i| self.at(i).is_focusable()),
+            None => None,
+        };
+        match next {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_first());
+                Response::Contained
+            },
+            None => Response::FocusNext,
         }
     }
 
-    fn move_left<'b>(&'b mut self) -> Response<'b> {
-        if self.focus().left >= 0 {
-            self.focus = self.focus().left as usize;
-            self.focus_mut().elem.enter_left();
-            Response::Contained
+    fn focus_prev(&mut self) -> Response {
+        let order = self.focus_order();
+        let end = order.iter().position(|&i| i == self.focus).unwrap_or(order.len());
+        let mut candidates = order[..end].iter().rev();
+        let prev = match candidates.find(|&&i| self.at(i).is_focusable()) {
+            Some(&i) => Some(i),
+            None if self.wrap => order.iter().rev().cloned().find(|&i| self.at(i).is_focusable()),
+            None => None,
+        };
+        match prev {
+            Some(i) => {
+                self.change_focus(i, |elem| elem.enter_last());
+                Response::Contained
+            },
+            None => Response::FocusPrev,
+        }
+    }
+
+    fn focus_order(&self) -> Vec<usize> {
+        match self.order {
+            Some(ref order) => order.clone(),
+            None => self.stack.clone(),
+        }
+    }
+
+    // Moves focus to the element at an index, telling both elements about it. The
+    // new element is entered before it hears that it gained focus, so that only
+    // whatever ends up focused inside it is told.
+    fn change_focus<F>(&mut self, i: usize, enter: F)
+        where F: FnOnce(&mut E)
+    {
+        self.focus_mut().elem.focus_lost();
+        self.focus = i;
+        enter(&mut self.focus_mut().elem);
+        self.focus_mut().elem.focus_gained()
+    }
+
+    // Focuses an element without entering it from any direction.
+    pub fn set_focus(&mut self, elem: ElemHandle) -> Result<(), InvalidHandle> {
+        self.holder(elem)?;
+        if elem.index != self.focus {
+            self.change_focus(elem.index, |_| ());
+        }
+        Ok(())
+    }
+
+    pub fn focused(&self) -> ElemHandle {
+        self.handle(self.focus)
+    }
+
+    // Returns the handle of the focused element, followed by the handles of the
+    // focused elements inside it if it is a grid, and so on.
+    pub fn focus_path(&self) -> Vec<ElemHandle> {
+        let mut path = Vec::new();
+        Element::extend_focus_path(self, &mut path);
+        path
+    }
+
+    fn neighbor(&self, link: isize, side: Side) -> Option<usize> {
+        if link >= 0 {
+            Some(link as usize)
+        } else if self.spatial {
+            self.nearest(side)
         } else {
-            Response::MoveLeft
+            None
+        }
+    }
+
+    // Finds the closest element on the given side of the focused one. Elements
+    // that lie straight across from it win over those that are off to a side.
+    fn nearest(&self, side: Side) -> Option<usize> {
+        let (grid_width, grid_height) = self.area.get();
+        let bounds = |holder: &ElemHolder<E>| {
+            let (width, height) = holder.extent(grid_width, grid_height);
+            (holder.x, holder.y, width.max(1), height.max(1))
+        };
+        let (fx, fy, fw, fh) = bounds(self.focus());
+        self.stack.iter().map(|&i| (i, self.at(i)))
+            .filter(|&(i, holder)| i != self.focus && holder.is_focusable())
+            .filter_map(|(i, holder)| {
+                let (x, y, w, h) = bounds(holder);
+                let (ahead, gap, cross) = match side {
+                    Side::Up    => (y < fy, fy.saturating_sub(y + h), ((x, w), (fx, fw))),
+                    Side::Down  => (y > fy, y.saturating_sub(fy + fh), ((x, w), (fx, fw))),
+                    Side::Right => (x > fx, x.saturating_sub(fx + fw), ((y, h), (fy, fh))),
+                    Side::Left  => (x < fx, fx.saturating_sub(x + w), ((y, h), (fy, fh))),
+                };
+                let ((start, len), (focus_start, focus_len)) = cross;
+                let off = if start + len <= focus_start {
+                    focus_start - (start + len) + 1
+                } else if focus_start + focus_len <= start {
+                    start - (focus_start + focus_len) + 1
+                } else {
+                    0
+                };
+                let skew = start.abs_diff(focus_start);
+                if ahead { Some(((off > 0, gap, off, skew), i)) } else { None }
+            })
+            .min()
+            .map(|(_, i)| i)
+    }
+
+    fn respond_with<F>(&mut self, respond: F) -> Response
+        where F: FnOnce(&mut E) -> Response
+    {
+        let focus = self.focus;
+        self.respond_at(focus, respond)
+    }
+
+    // Lets the element at an index respond. Only the focused element can move
+    // focus away from itself.
+    fn respond_at<F>(&mut self, i: usize, respond: F) -> Response
+        where F: FnOnce(&mut E) -> Response
+    {
+        match respond(&mut self.at_mut(i).elem) {
+            Response::MoveUp | Response::MoveDown | Response::MoveRight | Response::MoveLeft |
+            Response::FocusNext | Response::FocusPrev if i != self.focus => Response::Nothing,
+            Response::MoveUp     => self.move_up(),
+            Response::MoveDown   => self.move_down(),
+            Response::MoveRight  => self.move_right(),
+            Response::MoveLeft   => self.move_left(),
+            Response::FocusNext  => self.focus_next(),
+            Response::FocusPrev  => self.focus_prev(),
+            Response::Alert(a)   => {
+                self.alert_all(&a);
+                Response::Contained
+            },
+            Response::Message(mut m) => {
+                m.path.insert(0, self.handle(i));
+                Response::Message(m)
+            },
+            r => r,
+        }
+    }
+
+    // Finds the element at a position. Later elements are drawn over earlier
+    // ones, so they get the first chance.
+    fn elem_at(&self, x: usize, y: usize) -> Option<usize> {
+        let (grid_width, grid_height) = self.area.get();
+        self.stack.iter().cloned().rev().find(|&i| {
+            let holder = self.at(i);
+            let (width, height) = holder.extent(grid_width, grid_height);
+            x >= holder.x && x - holder.x < width &&
+            y >= holder.y && y - holder.y < height
+        })
+    }
+
+    fn route_mouse(&mut self, mouse: &MouseEvent, keymap: &Keymap) -> Response {
+        match self.elem_at(mouse.x, mouse.y) {
+            Some(i) => {
+                let (x, y) = (mouse.x - self.at(i).x, mouse.y - self.at(i).y);
+                // Only presses move focus, so that drags and the wheel do not.
+                if i != self.focus {
+                    if let MouseKind::Press(_) = mouse.kind {
+                        self.change_focus(i, |elem| elem.enter_at(x, y));
+                    }
+                }
+                let event = Event::Mouse(MouseEvent { x, y, ..*mouse });
+                self.respond_at(i, |elem| elem.respond_event(&event, keymap))
+            },
+            None => Response::Nothing,
         }
     }
 
     fn alert_all(&mut self, targets: &[ElemHandle]) {
         for t in targets {
-            if let Some(e) = self.elems.get_mut(t.0) {
+            if let Ok(e) = self.holder_mut(*t) {
                 e.elem.alert()
             }
         }
@@ -202,50 +741,153 @@ impl Display for InvalidHandle {
     }
 }
 
+// Handles stay tied to the element they were made for. Once it is removed, they
+// are invalid, even if a new element ends up in the same slot.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct ElemHandle(usize);
+pub struct ElemHandle {
+    index: usize,
+    generation: usize,
+}
+
+struct Slot<E: ?Sized> {
+    generation: usize,
+    holder: Option<ElemHolder<E>>,
+}
 
-struct ElemHolder<'a> {
-    elem: Box<Element<'a> + 'a>,
+impl<E: ?Sized> Slot<E> {
+    fn new(holder: ElemHolder<E>) -> Slot<E> {
+        Slot {
+            generation: 0,
+            holder: Some(holder),
+        }
+    }
+
+    fn get(&self, elem: ElemHandle) -> Option<&ElemHolder<E>> {
+        if elem.generation == self.generation { self.holder.as_ref() } else { None }
+    }
+
+    fn get_mut(&mut self, elem: ElemHandle) -> Option<&mut ElemHolder<E>> {
+        if elem.generation == self.generation { self.holder.as_mut() } else { None }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+struct ElemHolder<E: ?Sized> {
+    elem: Box<E>,
     x: usize,
     y: usize,
+    size: Option<(usize, usize)>,
+    // Set for elements added with `Grid::add`.
+    type_id: Option<TypeId>,
+    focusable: bool,
     up: isize,
     down: isize,
     right: isize,
     left: isize,
 }
 
-impl<'a> ElemHolder<'a> {
-    fn new(elem: Box<Element<'a> + 'a>, x: usize, y: usize) -> ElemHolder<'a> {
+impl<'a, E: ?Sized + Element<'a>> ElemHolder<E> {
+    fn new(elem: Box<E>, type_id: Option<TypeId>, x: usize, y: usize) -> ElemHolder<E> {
         ElemHolder {
-            elem, x, y,
+            elem, type_id, x, y,
+            size: None,
+            focusable: true,
             up: -1,
             down: -1,
             right: -1,
             left: -1,
         }
     }
+
+    fn is_focusable(&self) -> bool {
+        self.focusable && self.elem.focusable()
+    }
+
+    // Tells the element how much space it has. Elements outside the layout get
+    // whatever space lies below and to the right of them.
+    fn resize(&mut self, grid_width: usize, grid_height: usize) {
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => (grid_width.saturating_sub(self.x), grid_height.saturating_sub(self.y)),
+        };
+        self.elem.resize(width, height)
+    }
+
+    // The area the element may draw in: what the layout gave it, or else its
+    // preferred size, or else everything below and to the right of its position.
+    fn extent(&self, grid_width: usize, grid_height: usize) -> (usize, usize) {
+        if let Some(size) = self.size {
+            return size;
+        }
+        match self.elem.size_hint().preferred {
+            (0, 0) => (grid_width.saturating_sub(self.x), grid_height.saturating_sub(self.y)),
+            size => size,
+        }
+    }
 }
 
-impl<'a> Element<'a> for Grid<'a> {
-    fn draw(&self, canvas: &mut Canvas, x: usize, y: usize, selected: bool) {
-        for (i, &ElemHolder { ref elem, x: elem_x, y: elem_y, .. }) in self.elems.iter().enumerate() {
-            elem.draw(canvas, x + elem_x, y + elem_y, i == self.focus && selected)
+impl<'a, E: ?Sized + Element<'a> + 'a> Element<'a> for Grid<'a, E> {
+    fn draw(&self, canvas: &mut CanvasView, selected: bool) {
+        self.area.set((canvas.width(), canvas.height()));
+        for &i in &self.stack {
+            let holder = self.at(i);
+            let (width, height) = holder.extent(canvas.width(), canvas.height());
+            holder.elem.draw(&mut canvas.view(holder.x, holder.y, width, height), i == self.focus && selected)
         }
     }
 
     fn advance(&mut self) {
-        for &mut ElemHolder { ref mut elem, .. } in &mut self.elems {
-            elem.advance()
+        for holder in self.elems.iter_mut().filter_map(|slot| slot.holder.as_mut()) {
+            holder.elem.advance()
         }
     }
 
-    fn draw_advance(&mut self, canvas: &mut Canvas, x: usize, y: usize, selected: bool) {
-        for (i, &mut ElemHolder { ref mut elem, x: elem_x, y: elem_y, .. }) in self.elems.iter_mut().enumerate() {
-            elem.draw_advance(canvas, x + elem_x, y + elem_y, i == self.focus && selected)
+    fn resize(&mut self, width: usize, height: usize) {
+        self.size = (width, height);
+        self.area.set((width, height));
+        let rects = match self.layout {
+            Some(ref layout) => {
+                let elems = &self.elems;
+                let hint = |elem: ElemHandle| {
+                    elems.get(elem.index).and_then(|slot| slot.get(elem))
+                        .map_or_else(SizeHint::default, |holder| holder.elem.size_hint())
+                };
+                layout.compute(Rect { x: 0, y: 0, width, height }, &hint)
+            },
+            None => Vec::new(),
+        };
+        for (elem, rect) in rects {
+            if let Ok(holder) = self.holder_mut(elem) {
+                holder.x = rect.x;
+                holder.y = rect.y;
+                holder.size = Some((rect.width, rect.height));
+            }
+        }
+
+        for holder in self.elems.iter_mut().filter_map(|slot| slot.holder.as_mut()) {
+            holder.resize(width, height)
         }
     }
 
+    fn draw_advance(&mut self, canvas: &mut CanvasView, selected: bool) {
+        self.area.set((canvas.width(), canvas.height()));
+        let focus = self.focus;
+        for &i in &self.stack {
+            let holder = self.elems[i].holder.as_mut().unwrap();
+            let (width, height) = holder.extent(canvas.width(), canvas.height());
+            holder.elem.draw_advance(&mut canvas.view(holder.x, holder.y, width, height), i == focus && selected)
+        }
+    }
+
+    // Entering only moves focus inside the grid. Whoever moved focus onto the grid
+    // tells the elements about it afterwards.
     fn enter_top(&mut self) {
         self.focus = TL_IDX;
         self.focus_mut().elem.enter_top()
@@ -266,41 +908,67 @@ impl<'a> Element<'a> for Grid<'a> {
         self.focus_mut().elem.enter_left()
     }
 
-    fn respond<'b>(&'b mut self, input: char) -> Response<'b> {
-        let response = respond_raw_ptr(self.focus_mut().elem.borrow_mut(), input);
-        return match response {
-            RawPtrResponse::Contained => Response::Contained,
-            RawPtrResponse::Nothing   => Response::Nothing,
-            RawPtrResponse::MoveUp    => self.move_up(),
-            RawPtrResponse::MoveDown  => self.move_down(),
-            RawPtrResponse::MoveRight => self.move_right(),
-            RawPtrResponse::MoveLeft  => self.move_left(),
-            RawPtrResponse::Alert(a)  => {
-                self.alert_all(unsafe { &*a });
-                Response::Contained
-            },
-        };
+    fn enter_at(&mut self, x: usize, y: usize) {
+        if let Some(i) = self.elem_at(x, y) {
+            self.focus = i;
+            let holder = self.focus_mut();
+            holder.elem.enter_at(x - holder.x, y - holder.y)
+        }
+    }
 
-        enum RawPtrResponse {
-            Nothing,
-            Contained,
-            MoveUp,
-            MoveDown,
-            MoveRight,
-            MoveLeft,
-            Alert(*const [ElemHandle]),
-        }
-
-        fn respond_raw_ptr<'a, 'b>(this: &'b mut (Element<'a> + 'a), input: char) -> RawPtrResponse {
-            match this.respond(input) {
-                Response::Nothing   => RawPtrResponse::Nothing,
-                Response::Contained => RawPtrResponse::Contained,
-                Response::MoveUp    => RawPtrResponse::MoveUp,
-                Response::MoveDown  => RawPtrResponse::MoveDown,
-                Response::MoveRight => RawPtrResponse::MoveRight,
-                Response::MoveLeft  => RawPtrResponse::MoveLeft,
-                Response::Alert(a)  => RawPtrResponse::Alert(a),
-            }
+    fn focus_gained(&mut self) {
+        self.focus_mut().elem.focus_gained()
+    }
+
+    fn focus_lost(&mut self) {
+        self.focus_mut().elem.focus_lost()
+    }
+
+    fn extend_focus_path(&self, path: &mut Vec<ElemHandle>) {
+        path.push(self.focused());
+        self.focus().elem.extend_focus_path(path)
+    }
+
+    fn focusable(&self) -> bool {
+        self.focus_order().into_iter().any(|i| self.at(i).is_focusable())
+    }
+
+    fn enter_first(&mut self) {
+        if let Some(i) = self.focus_order().into_iter().find(|&i| self.at(i).is_focusable()) {
+            self.focus = i;
+            self.focus_mut().elem.enter_first()
+        }
+    }
+
+    fn enter_last(&mut self) {
+        if let Some(i) = self.focus_order().into_iter().rev().find(|&i| self.at(i).is_focusable()) {
+            self.focus = i;
+            self.focus_mut().elem.enter_last()
+        }
+    }
+
+    fn respond_event(&mut self, event: &Event, keymap: &Keymap) -> Response {
+        match *event {
+            Event::Mouse(ref mouse) => self.route_mouse(mouse, keymap),
+            _ => self.respond_with(|elem| elem.respond_event(event, keymap)),
+        }
+    }
+
+    fn respond_action(&mut self, action: Action) -> Response {
+        self.respond_with(|elem| elem.respond_action(action))
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let bounds = |size: &dyn Fn(&SizeHint) -> (usize, usize)| {
+            self.elems.iter().filter_map(|slot| slot.holder.as_ref()).fold((0, 0), |(width, height), holder| {
+                let (elem_width, elem_height) = size(&holder.elem.size_hint());
+                (width.max(holder.x + elem_width), height.max(holder.y + elem_height))
+            })
+        };
+        SizeHint {
+            min: bounds(&|h| h.min),
+            preferred: bounds(&|h| h.preferred),
+            ..SizeHint::default()
         }
     }
 }
@@ -308,9 +976,14 @@ impl<'a> Element<'a> for Grid<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use canvas::TextStyles;
+    use crate::canvas::{BoxStyle, TextStyles};
+    use crate::input::{KeyCode, Modifiers};
+    use crate::snapshot::assert_snapshot;
+    use crate::util::TextScroller;
 
+    use std::cell::RefCell;
     use std::marker::PhantomData;
+    use std::rc::Rc;
 
     #[test]
     fn grid_movement_works() {
@@ -319,8 +992,8 @@ mod tests {
         }
 
         impl<'a> Element<'a> for Foo<'a> {
-            fn draw(&self, canvas: &mut Canvas, x: usize, y: usize, selected: bool) {
-                canvas.text("foo", x, y, TextStyles::new().italics(selected))
+            fn draw(&self, canvas: &mut CanvasView, selected: bool) {
+                canvas.text("foo", 0, 0, TextStyles::new().italics(selected))
             }
         }
 
@@ -328,8 +1001,562 @@ mod tests {
         let top = grid.top_left();
         let bottom = grid.bottom_right();
         assert!(grid.connect_up_down(top, bottom).is_ok());
-        assert_eq!(grid.respond(DOWN), Response::Contained);
-        assert_eq!(grid.respond(DOWN), Response::MoveDown);
+        assert_eq!(grid.respond('j'), Response::Contained);
+        assert_eq!(grid.respond('j'), Response::MoveDown);
         assert_eq!(grid.respond('?'), Response::Nothing);
+        let up = Event::Key(KeyCode::Up.into());
+        assert_eq!(grid.respond_event(&up, &Keymap::default()), Response::Contained);
+        assert_eq!(grid.respond_event(&up, &Keymap::default()), Response::MoveUp);
+        assert_eq!(grid.respond_action(Action::MoveDown), Response::Contained);
+        let mut wasd = Keymap::wasd();
+        wasd.bind('x', Action::MoveUp);
+        assert_eq!(grid.respond_event(&Event::Key('j'.into()), &wasd), Response::Nothing);
+        assert_eq!(grid.respond_event(&Event::Key('x'.into()), &wasd), Response::Contained);
+    }
+
+    #[test]
+    fn spatial_navigation_works() {
+        struct Field(usize);
+
+        impl<'a> Element<'a> for Field {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn size_hint(&self) -> SizeHint { SizeHint::fixed(self.0, 1) }
+        }
+
+        // aaaa bb
+        //  cc  dddd
+        //
+        // eeeeeeeeee
+        let mut grid = Grid::with_capacity(Box::new(Field(4)), 0, 0, Box::new(Field(10)), 0, 3, 3);
+        let (a, e) = (grid.top_left(), grid.bottom_right());
+        let b = grid.add_elem(Box::new(Field(2)), 5, 0);
+        let c = grid.add_elem(Box::new(Field(2)), 1, 1);
+        let d = grid.add_elem(Box::new(Field(4)), 5, 1);
+        grid.resize(10, 4);
+        assert_eq!(grid.respond_action(Action::MoveRight), Response::MoveRight);
+        grid.set_spatial_navigation(true);
+        let path = [
+            (Action::MoveRight, b), (Action::MoveDown, d), (Action::MoveLeft, c), (Action::MoveUp, a),
+            (Action::MoveDown, c), (Action::MoveDown, e), (Action::MoveUp, c), (Action::MoveRight, d),
+        ];
+        for &(action, to) in &path {
+            assert_eq!(grid.respond_action(action), Response::Contained);
+            assert_eq!(grid.focus, to.index);
+        }
+        assert_eq!(grid.respond_action(Action::MoveRight), Response::MoveRight);
+
+        // Explicit connections still take priority.
+        assert!(grid.connect_up_down(a, d).is_ok());
+        assert_eq!(grid.respond_action(Action::MoveUp), Response::Contained);
+        assert_eq!(grid.focus, a.index);
+    }
+
+    #[test]
+    fn tab_order_works() {
+        use crate::harness::{Harness, Outcome, Script};
+
+        struct Field(char);
+
+        impl<'a> Element<'a> for Field {
+            fn draw(&self, canvas: &mut CanvasView, selected: bool) {
+                let ch = if selected { self.0.to_ascii_uppercase() } else { self.0 };
+                canvas.put(&ch.to_string(), 0, 0, TextStyles::new());
+            }
+
+            fn size_hint(&self) -> SizeHint { SizeHint::fixed(1, 1) }
+        }
+
+        let inner = Grid::with_capacity(Box::new(Field('a')), 0, 0, Box::new(Field('b')), 1, 0, 0);
+        let mut grid = Grid::with_capacity(Box::new(inner), 0, 0, Box::new(Field('e')), 4, 0, 2);
+        let (inner, e) = (grid.top_left(), grid.bottom_right());
+        let c = grid.add_elem(Box::new(Field('c')), 2, 0);
+        let d = grid.add_elem(Box::new(Field('d')), 3, 0);
+        grid.set_focus_order(vec![inner, c, d, e]).unwrap();
+        grid.set_focusable(d, false).unwrap();
+        let script = Script::new()
+            .frame(&["Abcde"])
+            .press(KeyCode::Tab, Outcome::Contained)
+            .frame(&["aBcde"])
+            .press(KeyCode::Tab, Outcome::Contained)
+            .frame(&["abCde"])
+            .press(KeyCode::Tab, Outcome::Contained)
+            .frame(&["abcdE"])
+            .press(KeyCode::Tab, Outcome::FocusNext)
+            .press(KeyCode::BackTab, Outcome::Contained)
+            .press(KeyCode::BackTab, Outcome::Contained)
+            .frame(&["aBcde"])
+            .press(KeyCode::BackTab, Outcome::Contained)
+            .press(KeyCode::BackTab, Outcome::FocusPrev)
+            .frame(&["Abcde"]);
+        assert_eq!(Harness::new(5, 1).run(&mut grid, &script), Ok(()));
+
+        grid.set_focus_wrap(true);
+        let script = Script::new()
+            .press(KeyCode::BackTab, Outcome::Contained)
+            .frame(&["abcdE"])
+            .press(KeyCode::Tab, Outcome::Contained)
+            .frame(&["Abcde"]);
+        assert_eq!(Harness::new(5, 1).run(&mut grid, &script), Ok(()));
+        assert!(grid.remove(d).is_ok());
+        assert_eq!(grid.set_focus_order(vec![inner, d]), Err(InvalidHandle(d)));
+    }
+
+    #[test]
+    fn removing_and_replacing_work() {
+        use crate::harness::{Harness, Outcome, Script};
+
+        struct Label(&'static str);
+
+        impl<'a> Element<'a> for Label {
+            fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+                canvas.text(self.0, 0, 0, TextStyles::new())
+            }
+
+            fn size_hint(&self) -> SizeHint { SizeHint::fixed(self.0.len(), 1) }
+        }
+
+        let mut grid = Grid::with_capacity(Box::new(Label("a")), 0, 0, Box::new(Label("b")), 0, 2, 1);
+        let (a, b) = (grid.top_left(), grid.bottom_right());
+        let c = grid.add_elem(Box::new(Label("c")), 0, 1);
+        grid.connect_up_down(a, c).unwrap();
+        grid.connect_up_down(c, b).unwrap();
+        assert_eq!(grid.remove(a).err(), Some(InvalidHandle(a)));
+        assert!(grid.remove(c).is_ok());
+        assert!(grid.remove(c).is_err());
+        assert!(grid.move_elem(c, 1, 1).is_err());
+
+        // The new element reuses the old one's slot, but the old handle stays dead.
+        let d = grid.add_elem(Box::new(Label("dd")), 0, 1);
+        assert_ne!(c, d);
+        assert_eq!(grid.connect_left_right(c, d), Err(InvalidHandle(c)));
+        let script = Script::new()
+            .frame(&["a ", "dd", "b "])
+            .press('j', Outcome::MoveDown);
+        assert_eq!(Harness::new(2, 3).run(&mut grid, &script), Ok(()));
+
+        assert!(grid.replace(a, Box::new(Label("xyz"))).is_ok());
+        grid.move_elem(d, 1, 0).unwrap();
+        let script = Script::new().frame(&["xdd", "   ", "b  "]);
+        assert_eq!(Harness::new(3, 3).run(&mut grid, &script), Ok(()));
+        grid.lower(d).unwrap();
+        let script = Script::new().frame(&["xyz", "   ", "b  "]);
+        assert_eq!(Harness::new(3, 3).run(&mut grid, &script), Ok(()));
+    }
+
+    #[test]
+    fn typed_access_works() {
+        struct Input(String);
+
+        impl<'a> Element<'a> for Input {
+            fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+                canvas.text(&self.0, 0, 0, TextStyles::new())
+            }
+        }
+
+        let mut grid = Grid::new_typed(Input("a".to_string()), 0, 0, TextScroller::new("b", 1, 1), 0, 1);
+        let name = grid.add(Input("name".to_string()), 2, 0);
+        let (tl, br) = (grid.top_left(), grid.bottom_right());
+        assert_eq!(grid.get::<Input>(tl).unwrap().0, "a");
+        assert!(grid.get::<TextScroller>(br).is_some());
+        assert!(grid.get::<TextScroller>(name).is_none());
+        grid.get_mut::<Input>(name).unwrap().0.push('d');
+        assert_eq!(grid.get::<Input>(name).unwrap().0, "named");
+
+        let positions: Vec<_> = grid.children().map(|(elem, x, y, _)| (elem, x, y)).collect();
+        assert_eq!(positions, vec![(tl, 0, 0), (br, 0, 1), (name, 2, 0)]);
+
+        grid.replace(name, Box::new(Input("other".to_string()))).unwrap();
+        assert!(grid.get::<Input>(name).is_none());
+        grid.replace_typed(br, Input("bottom".to_string())).unwrap();
+        assert_eq!(grid.get::<Input>(br).unwrap().0, "bottom");
+        assert!(grid.get::<TextScroller>(br).is_none());
+        let name = grid.add(Input("again".to_string()), 2, 0);
+        grid.remove(name).unwrap();
+        assert!(grid.get_mut::<Input>(name).is_none());
+    }
+
+    #[test]
+    fn focus_control_works() {
+        use crate::input::{MouseButton, MouseKind};
+
+        struct Field(&'static str, Rc<RefCell<Vec<String>>>);
+
+        impl<'a> Element<'a> for Field {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn size_hint(&self) -> SizeHint { SizeHint::fixed(1, 1) }
+
+            fn focus_gained(&mut self) { self.1.borrow_mut().push(format!("{}+", self.0)) }
+
+            fn focus_lost(&mut self) { self.1.borrow_mut().push(format!("{}-", self.0)) }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let events = || log.borrow_mut().drain(..).collect::<Vec<_>>();
+        let field = |name| Box::new(Field(name, log.clone()));
+        let inner = Grid::with_capacity(field("a"), 0, 0, field("b"), 1, 0, 0);
+        let (a, b) = (inner.top_left(), inner.bottom_right());
+        let mut grid = Grid::with_capacity(field("c"), 0, 0, Box::new(inner), 0, 1, 1);
+        let (tl, br) = (grid.top_left(), grid.bottom_right());
+        grid.connect_up_down(tl, br).unwrap();
+        let d = grid.add_elem(field("d"), 2, 0);
+        grid.resize(3, 2);
+        assert_eq!(grid.focused(), tl);
+        assert_eq!(grid.focus_path(), vec![tl]);
+
+        // Only the element that ends up focused inside a nested grid is told.
+        assert_eq!(grid.respond('j'), Response::Contained);
+        assert_eq!(grid.focus_path(), vec![br, b]);
+        assert_eq!(events(), vec!["c-", "b+"]);
+
+        grid.set_focus(d).unwrap();
+        assert_eq!(grid.focused(), d);
+        assert_eq!(events(), vec!["b-", "d+"]);
+        grid.connect_left_right(br, d).unwrap();
+        assert_eq!(grid.respond('h'), Response::Contained);
+        assert_eq!(grid.focus_path(), vec![br, a]);
+        assert_eq!(events(), vec!["d-", "a+"]);
+        assert_eq!(grid.respond('j'), Response::MoveDown);
+        assert!(events().is_empty());
+
+        let click = |x, y| Event::Mouse(MouseEvent {
+            kind: MouseKind::Press(MouseButton::Left),
+            x, y,
+            mods: Modifiers::NONE,
+        });
+        let keymap = Keymap::default();
+        grid.respond_event(&click(1, 1), &keymap);
+        assert_eq!(events(), vec!["a-", "b+"]);
+        grid.set_focus(d).unwrap();
+        assert_eq!(events(), vec!["b-", "d+"]);
+        grid.respond_event(&click(0, 1), &keymap);
+        assert_eq!(grid.focus_path(), vec![br, a]);
+        assert_eq!(events(), vec!["d-", "a+"]);
+
+        grid.remove(d).unwrap();
+        assert_eq!(grid.set_focus(d), Err(InvalidHandle(d)));
+        grid.set_focus(tl).unwrap();
+        assert_eq!(events(), vec!["a-", "c+"]);
+
+        // Replacing the focused element hands focus from the old one to the new one.
+        grid.replace(tl, field("e")).unwrap();
+        assert_eq!(events(), vec!["c-", "e+"]);
+        grid.replace(br, field("f")).unwrap();
+        assert!(events().is_empty());
+    }
+
+    #[test]
+    fn messages_bubble_up() {
+        use crate::util::Intercept;
+
+        struct Button(&'static str);
+
+        impl<'a> Element<'a> for Button {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn respond_action(&mut self, action: Action) -> Response {
+                match action {
+                    Action::Activate  => Response::Message(Message::new(self.0)),
+                    Action::MoveDown  => Response::MoveDown,
+                    Action::MoveRight => Response::MoveRight,
+                    _                 => Response::Nothing,
+                }
+            }
+        }
+
+        let mut inner = Grid::with_capacity(Box::new(Button("a")), 0, 0, Box::new(Button("b")), 1, 0, 0);
+        let (a, b) = (inner.top_left(), inner.bottom_right());
+        inner.connect_left_right(a, b).unwrap();
+        let seen = Rc::new(Cell::new(0));
+        let counter = seen.clone();
+        let inner = Intercept::new(inner, move |grid: &mut Grid, message: Message| {
+            counter.set(counter.get() + 1);
+            // Presses of "b" are handled here and go no further.
+            if message.downcast_ref::<&str>() == Some(&"b") {
+                grid.set_focus(grid.top_left()).unwrap();
+                None
+            } else {
+                Some(message)
+            }
+        });
+        let mut grid = Grid::with_capacity(Box::new(Button("c")), 0, 0, Box::new(inner), 0, 1, 0);
+        let (c, outer) = (grid.top_left(), grid.bottom_right());
+        grid.connect_up_down(c, outer).unwrap();
+
+        let response = grid.respond_action(Action::Activate);
+        assert_ne!(response, response);
+        match response {
+            Response::Message(message) => {
+                assert_eq!(message.path(), &[c][..]);
+                assert_eq!(message.downcast::<&str>().ok(), Some("c"));
+            },
+            r => panic!("Unexpected response {:?}", r),
+        }
+        // Coming in from above enters the inner grid at its bottom right.
+        assert_eq!(grid.respond('j'), Response::Contained);
+        assert_eq!(grid.focus_path(), vec![outer, b]);
+        assert_eq!(grid.respond_action(Action::Activate), Response::Contained);
+        assert_eq!(grid.focus_path(), vec![outer, a]);
+        match grid.respond_action(Action::Activate) {
+            Response::Message(message) => {
+                assert_eq!(message.path(), &[outer, a][..]);
+                assert!(message.is::<&str>());
+                assert!(message.downcast::<usize>().is_err());
+            },
+            r => panic!("Unexpected response {:?}", r),
+        }
+        assert_eq!(seen.get(), 2);
+    }
+
+    #[test]
+    fn alerts_work() {
+        struct Bell {
+            targets: Vec<ElemHandle>,
+            rung: usize,
+        }
+
+        impl<'a> Element<'a> for Bell {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn respond_action(&mut self, action: Action) -> Response {
+                match action {
+                    Action::Activate => Response::Alert(self.targets.clone()),
+                    _ => Response::Nothing,
+                }
+            }
+
+            fn alert(&mut self) {
+                self.rung += 1
+            }
+        }
+
+        let bell = || Bell { targets: Vec::new(), rung: 0 };
+        let mut inner: Grid<'static> = Grid::with_capacity(Box::new(bell()), 0, 0, Box::new(bell()), 0, 0, 2);
+        let first = inner.add(bell(), 0, 0);
+        let second = inner.add(bell(), 0, 0);
+        // The first bell rings itself as well as the second.
+        inner.get_mut::<Bell>(first).unwrap().targets = vec![first, second];
+        inner.set_focus(first).unwrap();
+        let mut grid = Grid::with_capacity(Box::new(bell()), 0, 0, Box::new(bell()), 0, 0, 1);
+        let nested = grid.add(inner, 0, 0);
+        grid.set_focus(nested).unwrap();
+
+        assert_eq!(grid.respond_action(Action::Activate), Response::Contained);
+        assert_eq!(grid.respond_action(Action::Activate), Response::Contained);
+        let inner = grid.get_mut::<Grid>(nested).unwrap();
+        assert_eq!(inner.get::<Bell>(first).unwrap().rung, 2);
+        assert_eq!(inner.get::<Bell>(second).unwrap().rung, 2);
+
+        // Handles to removed elements are skipped.
+        inner.remove(second).unwrap();
+        assert_eq!(grid.respond_action(Action::Activate), Response::Contained);
+        assert_eq!(grid.get::<Grid>(nested).unwrap().get::<Bell>(first).unwrap().rung, 3);
+    }
+
+    #[test]
+    fn send_grids_work() {
+        use std::thread;
+
+        let (mut grid, counter) = thread::spawn(|| {
+            let mut grid = SendGrid::new(Box::new(TextScroller::new("a\nb", 1, 1)), 0, 0,
+                                         Box::new(TextScroller::new("c", 1, 1)), 2, 0);
+            let counter = grid.add(TextScroller::new("1\n2\n3", 1, 1), 1, 0);
+            let (tl, br) = (grid.top_left(), grid.bottom_right());
+            grid.replace_typed(tl, TextScroller::new("a", 1, 1)).unwrap();
+            grid.connect_left_right(tl, counter).unwrap();
+            grid.connect_left_right(counter, br).unwrap();
+            (grid, counter)
+        }).join().unwrap();
+
+        assert_eq!(grid.respond('l'), Response::Contained);
+        assert_eq!(grid.respond('j'), Response::Contained);
+        assert!(grid.get::<TextScroller>(counter).is_some());
+        assert!(grid.get::<TextScroller>(grid.top_left()).is_some());
+        let mut canvas = Canvas::new(3, 1, ' ');
+        grid.draw(&mut canvas);
+        assert_eq!(canvas.to_string().lines().next(), Some("a2c"));
+    }
+
+    #[test]
+    fn panels_work() {
+        use crate::harness::{Harness, Outcome, Script};
+        use crate::input::MouseKind;
+        use crate::util::Panel;
+
+        let left = Panel::new(TextScroller::new("1\n2\n3", 4, 2)).title("ab").footer("x");
+        let right = Panel::new(TextScroller::new("4", 4, 2)).style(BoxStyle::Ascii);
+        assert_eq!(left.size_hint().preferred, (6, 4));
+        let mut grid = Grid::with_capacity(Box::new(left), 0, 0, Box::new(right), 7, 0, 0);
+        let (tl, br) = (grid.top_left(), grid.bottom_right());
+        grid.connect_left_right(tl, br).unwrap();
+        let wheel = |x, y| Event::Mouse(MouseEvent { kind: MouseKind::ScrollUp, x, y, mods: Modifiers::NONE });
+        let script = Script::new()
+            .frame(&["┏ ab ┓ +----+", "┃1   ┃ |4   |", "┃2   ┃ |    |", "┗ x ━┛ +----+"])
+            .press('j', Outcome::Contained)
+            .frame(&["┏ ab ┓ +----+", "┃2   ┃ |4   |", "┃3   ┃ |    |", "┗ x ━┛ +----+"])
+            .press('l', Outcome::Contained)
+            .frame(&["┌ ab ┐ ┏━━━━┓", "│2   │ ┃4   ┃", "│3   │ ┃    ┃", "└ x ─┘ ┗━━━━┛"])
+            // The grid is never resized here, so the panels go by the size they were drawn at.
+            .event(wheel(0, 1), Outcome::Nothing)
+            // The wheel scrolls whatever is under it without taking focus.
+            .event(wheel(1, 1), Outcome::Contained)
+            .frame(&["┌ ab ┐ ┏━━━━┓", "│1   │ ┃4   ┃", "│2   │ ┃    ┃", "└ x ─┘ ┗━━━━┛"]);
+        assert_eq!(Harness::new(13, 4).run(&mut grid, &script), Ok(()));
+    }
+
+    #[test]
+    fn grid_clips_children() {
+        struct Filler(char, SizeHint);
+
+        impl<'a> Element<'a> for Filler {
+            fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+                for y in 0..10 {
+                    canvas.line(self.0, 0, y, 10, TextStyles::new())
+                }
+            }
+
+            fn size_hint(&self) -> SizeHint { self.1 }
+        }
+
+        let mut grid = Grid::with_capacity(Box::new(Filler('a', SizeHint::fixed(2, 1))), 1, 1,
+                                           Box::new(Filler('b', SizeHint::default())), 4, 2, 1);
+        grid.add_elem(Box::new(Filler('c', SizeHint::fixed(3, 3))), 0, 2);
+        let mut canvas = Canvas::new(6, 4, '.');
+        grid.draw(&mut canvas);
+        assert_snapshot("grid_clips_children", &canvas);
+    }
+
+    #[test]
+    fn grid_layout_works() {
+        use crate::layout::Constraint;
+
+        struct Filler(char, SizeHint);
+
+        impl<'a> Element<'a> for Filler {
+            fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
+                for y in 0..canvas.height() {
+                    canvas.line(self.0, 0, y, canvas.width(), TextStyles::new())
+                }
+            }
+
+            fn size_hint(&self) -> SizeHint { self.1 }
+        }
+
+        let mut grid = Grid::with_capacity(Box::new(Filler('a', SizeHint::default())), 0, 0,
+                                           Box::new(Filler('b', SizeHint::flexible(2, 1))), 0, 0, 1);
+        let c = grid.add_elem(Box::new(Filler('c', SizeHint::default())), 0, 0);
+        let (a, b) = (grid.top_left(), grid.bottom_right());
+        grid.resize(10, 4);
+        grid.set_layout(Layout::row().padding(1).gap(1)
+                            .elem(a, Constraint::Flex(1))
+                            .nested(Layout::column()
+                                        .elem(b, Constraint::Auto)
+                                        .elem(c, Constraint::Flex(1)),
+                                    Constraint::Fixed(3)));
+        let mut canvas = Canvas::new(10, 4, '.');
+        grid.draw(&mut canvas);
+        assert_snapshot("grid_layout", &canvas);
+
+        grid.resize(7, 5);
+        let mut canvas = Canvas::new(7, 5, '.');
+        grid.draw(&mut canvas);
+        assert_snapshot("grid_layout_resized", &canvas);
+    }
+
+    #[test]
+    fn grid_resizing_works() {
+        struct Sized {
+            size: Rc<Cell<(usize, usize)>>,
+        }
+
+        impl<'a> Element<'a> for Sized {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn resize(&mut self, width: usize, height: usize) {
+                self.size.set((width, height))
+            }
+        }
+
+        let tl = Rc::new(Cell::new((0, 0)));
+        let br = Rc::new(Cell::new((0, 0)));
+        let mut grid = Grid::with_capacity(Box::new(Sized { size: tl.clone() }), 0, 0,
+                                           Box::new(Sized { size: br.clone() }), 5, 3, 0);
+        grid.resize(20, 10);
+        assert_eq!(tl.get(), (20, 10));
+        assert_eq!(br.get(), (15, 7));
+
+        let handle = grid.bottom_right();
+        assert!(grid.move_elem(handle, 25, 1).is_ok());
+        assert_eq!(br.get(), (0, 9));
+        assert!(grid.move_elem(handle, 5, 4).is_ok());
+        assert_eq!(br.get(), (15, 6));
+
+        // Elements added later are told their size right away.
+        let added = Rc::new(Cell::new((0, 0)));
+        grid.add_elem(Box::new(Sized { size: added.clone() }), 2, 2);
+        assert_eq!(added.get(), (18, 8));
+    }
+
+    #[test]
+    fn grid_mouse_routing_works() {
+        use crate::input::{MouseButton, MouseKind};
+
+        struct Clickable {
+            clicked_at: Rc<Cell<Option<(usize, usize)>>>,
+            hint: SizeHint,
+        }
+
+        impl<'a> Element<'a> for Clickable {
+            fn draw(&self, _canvas: &mut CanvasView, _selected: bool) { }
+
+            fn size_hint(&self) -> SizeHint { self.hint }
+
+            fn respond_mouse(&mut self, mouse: &MouseEvent) -> Response {
+                self.clicked_at.set(Some((mouse.x, mouse.y)));
+                Response::Contained
+            }
+        }
+
+        let left = Rc::new(Cell::new(None));
+        let right = Rc::new(Cell::new(None));
+        let clickable = |clicked_at: &Rc<Cell<Option<(usize, usize)>>>, hint| Box::new(Clickable { clicked_at: clicked_at.clone(), hint });
+        let mut grid = Grid::with_capacity(clickable(&left, SizeHint::fixed(3, 2)), 0, 0,
+                                           clickable(&right, SizeHint::fixed(3, 2)), 5, 1, 0);
+        assert_eq!(grid.size_hint().preferred, (8, 3));
+
+        let click = |x, y| Event::Mouse(MouseEvent {
+            kind: MouseKind::Press(MouseButton::Left),
+            x, y,
+            mods: Modifiers::NONE,
+        });
+        let keymap = Keymap::default();
+        assert_eq!(grid.respond_event(&click(6, 2), &keymap), Response::Contained);
+        assert_eq!(right.get(), Some((1, 1)));
+        assert_eq!(grid.focus, BR_IDX);
+        assert_eq!(grid.respond_event(&click(4, 0), &keymap), Response::Nothing);
+        assert_eq!(grid.focus, BR_IDX);
+        assert_eq!(grid.respond_event(&click(2, 1), &keymap), Response::Contained);
+        assert_eq!(left.get(), Some((2, 1)));
+        assert_eq!(grid.focus, TL_IDX);
+
+        // Anything but a press reaches the element under it without focusing it.
+        let release = Event::Mouse(MouseEvent {
+            kind: MouseKind::Release(MouseButton::Left),
+            x: 7, y: 1,
+            mods: Modifiers::NONE,
+        });
+        assert_eq!(grid.respond_event(&release, &keymap), Response::Contained);
+        assert_eq!(right.get(), Some((2, 0)));
+        assert_eq!(grid.focus, TL_IDX);
+
+        // Without a preferred size, an element can be clicked anywhere it was
+        // drawn, even if the grid was never resized.
+        let mut grid = Grid::with_capacity(clickable(&left, SizeHint::fixed(3, 2)), 0, 0,
+                                           clickable(&right, SizeHint::default()), 4, 0, 0);
+        grid.draw(&mut Canvas::new(8, 3, ' '));
+        assert_eq!(grid.respond_event(&click(7, 2), &keymap), Response::Contained);
+        assert_eq!(right.get(), Some((3, 2)));
+        assert_eq!(grid.focus, BR_IDX);
     }
 }
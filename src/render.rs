@@ -0,0 +1,106 @@
+use crate::canvas::{Canvas, TextStyles};
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+
+pub struct Renderer {
+    previous: Option<Canvas>,
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Renderer { previous: None }
+    }
+
+    // Forgets the last flushed frame so that the next render repaints every cell.
+    pub fn invalidate(&mut self) {
+        self.previous = None
+    }
+
+    pub fn render<W: Write>(&mut self, canvas: &Canvas, out: &mut W) -> io::Result<()> {
+        let mut buf = String::new();
+        let previous = match self.previous {
+            Some(ref p) if p.width() == canvas.width() && p.height() == canvas.height() => Some(p),
+            _ => {
+                buf.push_str("\x1B[0m\x1B[2J");
+                None
+            }
+        };
+
+        // Every frame ends with the styles reset, so each one starts from the defaults.
+        let mut styles = TextStyles::new();
+        let mut cursor = None;
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                let p = unsafe { canvas.get_unchecked(x, y) };
+                // Continuation pixels are drawn along with the wide character before them.
+                if p.continuation {
+                    continue;
+                }
+                if let Some(previous) = previous {
+                    if unsafe { previous.get_unchecked(x, y) } == p {
+                        continue;
+                    }
+                }
+                if cursor != Some((x, y)) {
+                    write!(buf, "\x1B[{};{}H", y + 1, x + 1).unwrap();
+                }
+                styles.write_transition(p.styles, &mut buf).unwrap();
+                styles = p.styles;
+                p.write_text(&mut buf).unwrap();
+                cursor = Some((x + p.width(), y));
+            }
+        }
+        styles.write_transition(TextStyles::new(), &mut buf).unwrap();
+
+        out.write_all(buf.as_bytes())?;
+        out.flush()?;
+        self.previous = Some(canvas.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Color;
+
+    #[test]
+    fn first_render_is_full() {
+        let mut r = Renderer::new();
+        let mut c = Canvas::new(2, 2, '.');
+        c.text("a", 1, 1, TextStyles::new().bold(true));
+        let mut out = Vec::new();
+        r.render(&c, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "\x1B[0m\x1B[2J\x1B[1;1H..\x1B[2;1H.\x1B[1ma\x1B[0m");
+    }
+
+    #[test]
+    fn later_renders_only_send_changes() {
+        let mut r = Renderer::new();
+        let mut c = Canvas::new(5, 2, ' ');
+        r.render(&c, &mut Vec::new()).unwrap();
+
+        let mut out = Vec::new();
+        r.render(&c, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        c.text("hi", 1, 0, TextStyles::new().fg(Color::Red));
+        c.text("!", 4, 1, TextStyles::new());
+        let mut out = Vec::new();
+        r.render(&c, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\x1B[1;2H\x1B[31mhi\x1B[2;5H\x1B[0m!");
+
+        r.invalidate();
+        let mut out = Vec::new();
+        r.render(&c, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().starts_with("\x1B[0m\x1B[2J"));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::canvas::{Canvas, Color, TextStyles};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Set this environment variable to rewrite snapshots instead of checking them.
+pub const BLESS_VAR: &str = "UI_BLESS";
+
+const STYLE_KEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+// Writes the characters of a canvas, followed by a map of which styles each
+// pixel has if any are styled. Unstyled pixels are '.' in the map, which has
+// one entry per column even where a wide character covers two.
+pub fn dump(canvas: &Canvas) -> String {
+    let mut chars = String::new();
+    let mut map = String::new();
+    let mut styles: Vec<TextStyles> = Vec::new();
+
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            let p = canvas.get(x, y).unwrap();
+            p.write_text(&mut chars).unwrap();
+            if p.styles == TextStyles::new() {
+                map.push('.');
+            } else {
+                let i = match styles.iter().position(|&s| s == p.styles) {
+                    Some(i) => i,
+                    None => {
+                        styles.push(p.styles);
+                        styles.len() - 1
+                    },
+                };
+                map.push(STYLE_KEYS.chars().nth(i).unwrap_or('?'));
+            }
+        }
+        chars.push('\n');
+        map.push('\n');
+    }
+
+    if !styles.is_empty() {
+        chars.push_str("--\n");
+        chars.push_str(&map);
+        chars.push_str("--\n");
+        for (key, s) in STYLE_KEYS.chars().zip(&styles) {
+            chars.push_str(&format!("{}: {}\n", key, describe(*s)));
+        }
+    }
+    chars
+}
+
+fn describe(styles: TextStyles) -> String {
+    let mut parts = Vec::new();
+    if styles.is_bold() {
+        parts.push("bold".to_string());
+    }
+    if styles.is_italics() {
+        parts.push("italics".to_string());
+    }
+    if styles.is_underline() {
+        parts.push("underline".to_string());
+    }
+    if styles.is_inverse() {
+        parts.push("inverse".to_string());
+    }
+    if styles.fg_color() != Color::Default {
+        parts.push(format!("fg={:?}", styles.fg_color()));
+    }
+    if styles.bg_color() != Color::Default {
+        parts.push(format!("bg={:?}", styles.bg_color()));
+    }
+    parts.join(" ")
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let mut path = env::var_os("CARGO_MANIFEST_DIR").map_or_else(PathBuf::new, PathBuf::from);
+    path.push("snapshots");
+    path.push(format!("{}.snap", name));
+    path
+}
+
+// Compares the canvas against snapshots/<name>.snap in the crate being tested.
+pub fn assert_snapshot(name: &str, canvas: &Canvas) {
+    let actual = dump(canvas);
+    let path = snapshot_path(name);
+
+    if env::var_os(BLESS_VAR).is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = match fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(e) => panic!("Could not read snapshot {}: {}\nRun with {}=1 to create it. Actual:\n{}",
+                         path.display(), e, BLESS_VAR, actual),
+    };
+    if expected != actual {
+        panic!("Snapshot {} does not match (! marks differing lines). Run with {}=1 to update it.\n{}",
+               path.display(), BLESS_VAR, side_by_side(&expected, &actual));
+    }
+}
+
+fn side_by_side(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let width = expected.iter().map(|l| l.chars().count()).max().unwrap_or(0).max("expected".len());
+
+    let mut out = format!("  {:<width$} | actual\n", "expected", width = width);
+    for i in 0..expected.len().max(actual.len()) {
+        let e = expected.get(i).cloned().unwrap_or("");
+        let a = actual.get(i).cloned().unwrap_or("");
+        let marker = if expected.get(i) == actual.get(i) { ' ' } else { '!' };
+        let padding = width - e.chars().count();
+        out.push_str(&format!("{} {}{} | {}\n", marker, e, " ".repeat(padding), a));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dumping_works() {
+        let mut c = Canvas::new(4, 2, ' ');
+        assert_eq!(dump(&c), "    \n    \n");
+        c.text("ab", 0, 0, TextStyles::new().bold(true).fg(Color::Red));
+        c.text("c", 1, 1, TextStyles::new().bg(Color::Indexed(3)));
+        c.text("d", 3, 1, TextStyles::new().bold(true).fg(Color::Red));
+        assert_eq!(dump(&c), "ab  \n c d\n--\naa..\n.b.a\n--\na: bold fg=Red\nb: bg=Indexed(3)\n");
+    }
+
+    #[test]
+    fn diffs_mark_changed_lines() {
+        assert_eq!(side_by_side("ab\ncd\n", "ab\nce\nf\n"),
+                   "  expected | actual\n  ab       | ab\n! cd       | ce\n!          | f\n");
+    }
+}
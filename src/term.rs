@@ -0,0 +1,181 @@
+use crate::app::Backend;
+
+use std::io::{self, Write};
+use std::mem;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+// The terminal settings from before raw mode was entered, if a Terminal is active.
+static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+static HOOK: Once = Once::new();
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+const ENTER: &[u8] = b"\x1B[?1049h\x1B[?25l";
+const LEAVE: &[u8] = b"\x1B[0m\x1B[?2004l\x1B[?1006l\x1B[?1002l\x1B[?1000l\x1B[?25h\x1B[?1049l";
+
+pub struct Terminal {
+    buf: Vec<u8>,
+}
+
+impl Terminal {
+    pub fn new() -> io::Result<Terminal> {
+        let mut original = lock_original();
+        if original.is_some() {
+            return Err(io::Error::other("A Terminal is already active"));
+        }
+
+        let mut termios: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = termios;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        *original = Some(termios);
+        drop(original);
+
+        HOOK.call_once(|| {
+            unsafe {
+                libc::signal(libc::SIGWINCH, on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            }
+
+            // Put the terminal back before the panic message is printed, not after.
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore();
+                previous(info)
+            }));
+        });
+
+        let mut term = Terminal { buf: Vec::new() };
+        term.write_all(ENTER)?;
+        term.flush()?;
+        Ok(term)
+    }
+
+    // Returns the width and height of the terminal in cells.
+    pub fn size(&self) -> io::Result<(usize, usize)> {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((size.ws_col as usize, size.ws_row as usize))
+    }
+
+    pub fn hide_cursor(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?25l")
+    }
+
+    pub fn show_cursor(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?25h")
+    }
+
+    // Asks for SGR-encoded reports of clicks, drags and the scroll wheel.
+    pub fn enable_mouse(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?1000h\x1B[?1002h\x1B[?1006h")
+    }
+
+    pub fn disable_mouse(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?1006l\x1B[?1002l\x1B[?1000l")
+    }
+
+    // Asks for pasted text to be marked so that it arrives as one Paste event
+    // instead of as keys.
+    pub fn enable_paste(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?2004h")
+    }
+
+    pub fn disable_paste(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1B[?2004l")
+    }
+
+    pub fn move_cursor(&mut self, x: usize, y: usize) -> io::Result<()> {
+        write!(self, "\x1B[{};{}H", y + 1, x + 1)
+    }
+}
+
+impl Backend for Terminal {
+    fn size(&self) -> io::Result<(usize, usize)> {
+        Terminal::size(self)
+    }
+
+    fn read_input(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_secs() as i64 * 1000 + timeout.subsec_nanos() as i64 / 1_000_000;
+        let millis = millis.min(libc::c_int::MAX as i64) as libc::c_int;
+        match unsafe { libc::poll(&mut fd, 1, millis) } {
+            0 => return Ok(0),
+            n if n < 0 => return interrupted_as_empty(io::Error::last_os_error()),
+            _ => (),
+        }
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n < 0 {
+            interrupted_as_empty(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn resized(&mut self) -> bool {
+        RESIZED.swap(false, Ordering::SeqCst)
+    }
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(&self.buf)?;
+        self.buf.clear();
+        stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        restore()
+    }
+}
+
+extern "C" fn on_resize(_signal: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst)
+}
+
+// Signals such as SIGWINCH interrupt waiting for input; that is not an error.
+fn interrupted_as_empty(err: io::Error) -> io::Result<usize> {
+    if err.kind() == io::ErrorKind::Interrupted {
+        Ok(0)
+    } else {
+        Err(err)
+    }
+}
+
+fn lock_original() -> ::std::sync::MutexGuard<'static, Option<libc::termios>> {
+    ORIGINAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn restore() {
+    if let Some(termios) = lock_original().take() {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let _ = stdout.write_all(LEAVE);
+        let _ = stdout.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &termios);
+        }
+    }
+}
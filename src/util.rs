@@ -1,6 +1,10 @@
-use ::{Element, Response, UP, DOWN, RIGHT, LEFT};
-use canvas::{Canvas, TextStyles};
+use crate::{Element, ElemHandle, Message, Response};
+use crate::canvas::{BoxStyle, CanvasView, TextStyles};
+use crate::input::{Event, MouseEvent, MouseKind};
+use crate::keymap::{Action, Keymap};
+use crate::layout::SizeHint;
 
+use std::cell::Cell;
 use std::marker::PhantomData;
 
 pub struct Updater<'a, E>
@@ -26,19 +30,33 @@ impl<'a, E> Updater<'a, E>
 impl<'a, E> Element<'a> for Updater<'a, E>
     where E: Element<'a>
 {
-    fn draw(&self, canvas: &mut Canvas, x: usize, y: usize, selected: bool) {
+    fn draw(&self, canvas: &mut CanvasView, selected: bool) {
         if self.updated {
-            self.inner.draw(canvas, x, y, selected)
+            self.inner.draw(canvas, selected)
         }
     }
 
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+
     fn advance(&mut self) {
         self.inner.advance();
         self.updated = false
     }
 
-    fn respond<'b>(&'b mut self, input: char) -> Response<'b> {
-        match self.inner.respond(input) {
+    fn respond_event(&mut self, event: &Event, keymap: &Keymap) -> Response {
+        match self.inner.respond_event(event, keymap) {
+            Response::Nothing => Response::Nothing,
+            r => {
+                self.updated = true;
+                r
+            }
+        }
+    }
+
+    fn respond_action(&mut self, action: Action) -> Response {
+        match self.inner.respond_action(action) {
             Response::Nothing => Response::Nothing,
             r => {
                 self.updated = true;
@@ -47,6 +65,11 @@ impl<'a, E> Element<'a> for Updater<'a, E>
         }
     }
 
+    fn resize(&mut self, width: usize, height: usize) {
+        self.updated = true;
+        self.inner.resize(width, height)
+    }
+
     fn enter_top(&mut self) {
         self.updated = true;
         self.inner.enter_top()
@@ -67,12 +90,368 @@ impl<'a, E> Element<'a> for Updater<'a, E>
         self.inner.enter_left()
     }
 
+    fn enter_at(&mut self, x: usize, y: usize) {
+        self.updated = true;
+        self.inner.enter_at(x, y)
+    }
+
+    fn focusable(&self) -> bool {
+        self.inner.focusable()
+    }
+
+    fn enter_first(&mut self) {
+        self.updated = true;
+        self.inner.enter_first()
+    }
+
+    fn enter_last(&mut self) {
+        self.updated = true;
+        self.inner.enter_last()
+    }
+
+    fn focus_gained(&mut self) {
+        self.updated = true;
+        self.inner.focus_gained()
+    }
+
+    fn focus_lost(&mut self) {
+        self.updated = true;
+        self.inner.focus_lost()
+    }
+
+    fn extend_focus_path(&self, path: &mut Vec<ElemHandle>) {
+        self.inner.extend_focus_path(path)
+    }
+
     fn alert(&mut self) {
         self.updated = true;
         self.inner.alert()
     }
 }
 
+// Lets a parent see the messages coming out of an element before they go any
+// further. The handler can act on a message and return None to stop it, or
+// return it to let it keep going.
+pub struct Intercept<'a, E, F>
+    where E: Element<'a>,
+          F: FnMut(&mut E, Message) -> Option<Message>
+{
+    inner: E,
+    handler: F,
+    _a: PhantomData<&'a ()>,
+}
+
+impl<'a, E, F> Intercept<'a, E, F>
+    where E: Element<'a>,
+          F: FnMut(&mut E, Message) -> Option<Message>
+{
+    pub fn new(elem: E, handler: F) -> Intercept<'a, E, F> {
+        Intercept {
+            inner: elem,
+            handler,
+            _a: PhantomData,
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    fn intercept(&mut self, message: Message) -> Response {
+        match (self.handler)(&mut self.inner, message) {
+            Some(message) => Response::Message(message),
+            None => Response::Contained,
+        }
+    }
+}
+
+impl<'a, E, F> Element<'a> for Intercept<'a, E, F>
+    where E: Element<'a>,
+          F: FnMut(&mut E, Message) -> Option<Message>
+{
+    fn draw(&self, canvas: &mut CanvasView, selected: bool) {
+        self.inner.draw(canvas, selected)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+
+    fn advance(&mut self) {
+        self.inner.advance()
+    }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        self.inner.resize(width, height)
+    }
+
+    fn draw_advance(&mut self, canvas: &mut CanvasView, selected: bool) {
+        self.inner.draw_advance(canvas, selected)
+    }
+
+    fn respond_event(&mut self, event: &Event, keymap: &Keymap) -> Response {
+        match self.inner.respond_event(event, keymap) {
+            Response::Message(message) => self.intercept(message),
+            r => r,
+        }
+    }
+
+    fn respond_action(&mut self, action: Action) -> Response {
+        match self.inner.respond_action(action) {
+            Response::Message(message) => self.intercept(message),
+            r => r,
+        }
+    }
+
+    fn enter_top(&mut self) {
+        self.inner.enter_top()
+    }
+
+    fn enter_bottom(&mut self) {
+        self.inner.enter_bottom()
+    }
+
+    fn enter_right(&mut self) {
+        self.inner.enter_right()
+    }
+
+    fn enter_left(&mut self) {
+        self.inner.enter_left()
+    }
+
+    fn enter_at(&mut self, x: usize, y: usize) {
+        self.inner.enter_at(x, y)
+    }
+
+    fn focusable(&self) -> bool {
+        self.inner.focusable()
+    }
+
+    fn enter_first(&mut self) {
+        self.inner.enter_first()
+    }
+
+    fn enter_last(&mut self) {
+        self.inner.enter_last()
+    }
+
+    fn focus_gained(&mut self) {
+        self.inner.focus_gained()
+    }
+
+    fn focus_lost(&mut self) {
+        self.inner.focus_lost()
+    }
+
+    fn extend_focus_path(&self, path: &mut Vec<ElemHandle>) {
+        self.inner.extend_focus_path(path)
+    }
+
+    fn alert(&mut self) {
+        self.inner.alert()
+    }
+}
+
+// Draws a frame around an element, with an optional title along the top and
+// footer along the bottom. The frame is drawn differently while the element is
+// selected.
+pub struct Panel<'a, E>
+    where E: Element<'a>
+{
+    inner: E,
+    title: Option<String>,
+    footer: Option<String>,
+    style: BoxStyle,
+    selected_style: BoxStyle,
+    // The size the panel was last drawn or resized at, for telling where the
+    // frame is.
+    size: Cell<(usize, usize)>,
+    _a: PhantomData<&'a ()>,
+}
+
+impl<'a, E> Panel<'a, E>
+    where E: Element<'a>
+{
+    pub fn new(elem: E) -> Panel<'a, E> {
+        Panel {
+            inner: elem,
+            title: None,
+            footer: None,
+            style: BoxStyle::Single,
+            selected_style: BoxStyle::Heavy,
+            size: Cell::new((0, 0)),
+            _a: PhantomData,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Panel<'a, E> {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn footer(mut self, footer: &str) -> Panel<'a, E> {
+        self.footer = Some(footer.to_string());
+        self
+    }
+
+    pub fn style(mut self, style: BoxStyle) -> Panel<'a, E> {
+        self.style = style;
+        self
+    }
+
+    pub fn selected_style(mut self, style: BoxStyle) -> Panel<'a, E> {
+        self.selected_style = style;
+        self
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut E {
+        &mut self.inner
+    }
+
+    // Draws the frame and returns the area inside it.
+    fn draw_frame<'v>(&self, canvas: &'v mut CanvasView, selected: bool) -> CanvasView<'v> {
+        let (width, height) = (canvas.width(), canvas.height());
+        self.size.set((width, height));
+        let (style, styles) = if selected {
+            (self.selected_style, TextStyles::new().bold(true))
+        } else {
+            (self.style, TextStyles::new())
+        };
+        // Blank the border first so the frame is not merged with the one drawn
+        // last time, which may have been in another style.
+        canvas.rect(' ', 0, 0, width, height, styles);
+        canvas.frame(0, 0, width, height, style, styles);
+        let labels = [(&self.title, 0), (&self.footer, height.saturating_sub(1))];
+        for &(label, y) in &labels {
+            if let Some(ref label) = *label {
+                canvas.write_line(&format!(" {} ", label), 1, y, width.saturating_sub(2), styles);
+            }
+        }
+        canvas.view(1, 1, width.saturating_sub(2), height.saturating_sub(2))
+    }
+
+    // Whether a position is inside the frame rather than on it.
+    fn within_frame(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.size.get();
+        x > 0 && y > 0 && x + 1 < width && y + 1 < height
+    }
+}
+
+impl<'a, E> Element<'a> for Panel<'a, E>
+    where E: Element<'a>
+{
+    fn draw(&self, canvas: &mut CanvasView, selected: bool) {
+        let mut inner = self.draw_frame(canvas, selected);
+        self.inner.draw(&mut inner, selected)
+    }
+
+    // Unknown preferred sizes stay unknown so that the panel still fills whatever
+    // space it is given.
+    fn size_hint(&self) -> SizeHint {
+        let hint = self.inner.size_hint();
+        let grow = |(width, height): (usize, usize)| (width.saturating_add(2), height.saturating_add(2));
+        SizeHint {
+            min: grow(hint.min),
+            preferred: if hint.preferred == (0, 0) { (0, 0) } else { grow(hint.preferred) },
+            max: grow(hint.max),
+        }
+    }
+
+    fn advance(&mut self) {
+        self.inner.advance()
+    }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        self.size.set((width, height));
+        self.inner.resize(width.saturating_sub(2), height.saturating_sub(2))
+    }
+
+    fn draw_advance(&mut self, canvas: &mut CanvasView, selected: bool) {
+        let mut inner = self.draw_frame(canvas, selected);
+        self.inner.draw_advance(&mut inner, selected)
+    }
+
+    // Mouse events on the frame itself go nowhere.
+    fn respond_event(&mut self, event: &Event, keymap: &Keymap) -> Response {
+        match *event {
+            Event::Mouse(ref mouse) => {
+                if !self.within_frame(mouse.x, mouse.y) {
+                    return Response::Nothing;
+                }
+                let event = Event::Mouse(MouseEvent {
+                    x: mouse.x - 1,
+                    y: mouse.y - 1,
+                    ..*mouse
+                });
+                self.inner.respond_event(&event, keymap)
+            },
+            _ => self.inner.respond_event(event, keymap),
+        }
+    }
+
+    fn respond_action(&mut self, action: Action) -> Response {
+        self.inner.respond_action(action)
+    }
+
+    fn enter_top(&mut self) {
+        self.inner.enter_top()
+    }
+
+    fn enter_bottom(&mut self) {
+        self.inner.enter_bottom()
+    }
+
+    fn enter_right(&mut self) {
+        self.inner.enter_right()
+    }
+
+    fn enter_left(&mut self) {
+        self.inner.enter_left()
+    }
+
+    fn enter_at(&mut self, x: usize, y: usize) {
+        if self.within_frame(x, y) {
+            self.inner.enter_at(x - 1, y - 1)
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        self.inner.focusable()
+    }
+
+    fn enter_first(&mut self) {
+        self.inner.enter_first()
+    }
+
+    fn enter_last(&mut self) {
+        self.inner.enter_last()
+    }
+
+    fn focus_gained(&mut self) {
+        self.inner.focus_gained()
+    }
+
+    fn focus_lost(&mut self) {
+        self.inner.focus_lost()
+    }
+
+    fn extend_focus_path(&self, path: &mut Vec<ElemHandle>) {
+        self.inner.extend_focus_path(path)
+    }
+
+    fn alert(&mut self) {
+        self.inner.alert()
+    }
+}
+
 pub struct TextScroller<'a> {
     lines: Vec<&'a str>,
     width: usize,
@@ -106,60 +485,76 @@ impl<'a> TextScroller<'a> {
             Response::MoveDown
         }
     }
+
+    pub fn page_up(&mut self) -> Response {
+        self.window = self.window.saturating_sub(self.height);
+        Response::Contained
+    }
+
+    pub fn page_down(&mut self) -> Response {
+        let last = self.lines.len().saturating_sub(self.height);
+        self.window = (self.window + self.height).min(last);
+        Response::Contained
+    }
 }
 
 impl<'a> Element<'a> for TextScroller<'a> {
-    fn draw(&self, canvas: &mut Canvas, x: usize, y: usize, _selected: bool) {
+    fn draw(&self, canvas: &mut CanvasView, _selected: bool) {
         if self.lines.len() < self.height {
             for (i, l) in self.lines.iter().enumerate() {
-                canvas.text(l, x, y + i, TextStyles::new())
+                canvas.write_line(l, 0, i, self.width, TextStyles::new());
             }
         } else {
             for (i, l) in self.lines[self.window..self.window + self.height].iter().enumerate() {
-                padded_line(canvas, l, x, y + i, self.width, ' ', TextStyles::new())
+                padded_line(canvas, l, 0, i, self.width, ' ', TextStyles::new())
             }
         }
     }
 
-    fn respond(&mut self, input: char) -> Response {
-        match input {
-            UP    => self.scroll_up(),
-            DOWN  => self.scroll_down(),
-            RIGHT => Response::MoveRight,
-            LEFT  => Response::MoveLeft,
+    fn respond_action(&mut self, action: Action) -> Response {
+        match action {
+            Action::MoveUp     => self.scroll_up(),
+            Action::MoveDown   => self.scroll_down(),
+            Action::MoveRight  => Response::MoveRight,
+            Action::MoveLeft   => Response::MoveLeft,
+            Action::FocusNext  => Response::FocusNext,
+            Action::FocusPrev  => Response::FocusPrev,
+            Action::ScrollUp   => {
+                self.scroll_up();
+                Response::Contained
+            },
+            Action::ScrollDown => {
+                self.scroll_down();
+                Response::Contained
+            },
+            Action::PageUp     => self.page_up(),
+            Action::PageDown   => self.page_down(),
             _ => Response::Nothing,
         }
     }
-}
 
-fn padded_line<'a>(canvas: &mut Canvas, text: &'a str, x: usize, y: usize, length: usize, pad: char, styles: TextStyles) {
-        if x >= canvas.width() || y >= canvas.height() {
-            return;
-        }
-        let length = if x + length > canvas.width() {
-            canvas.width() - x
-        } else {
-            length
-        };
-        let mut current_x = x;
-
-        unsafe {
-            canvas.get_unchecked_mut(current_x, y).set_styles_on(styles);
-        }
-
-        let mut space_left = length;
-
-        for letter in text.chars().take(length) {
-            unsafe {
-                canvas.get_unchecked_mut(current_x, y).ch = letter;
-            }
-            current_x += 1;
-            space_left -= 1;
+    fn respond_mouse(&mut self, mouse: &MouseEvent) -> Response {
+        // The wheel should not move focus out of the scroller at either end.
+        match mouse.kind {
+            MouseKind::ScrollUp => {
+                self.scroll_up();
+                Response::Contained
+            },
+            MouseKind::ScrollDown => {
+                self.scroll_down();
+                Response::Contained
+            },
+            MouseKind::Press(_) => Response::Contained,
+            _ => Response::Nothing,
         }
+    }
 
-        unsafe {
-            canvas.get_unchecked_mut(current_x, y).set_styles_off(styles);
-        }
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::fixed(self.width, self.height)
+    }
+}
 
-        canvas.line(pad, current_x, y, space_left, styles)
+fn padded_line(canvas: &mut CanvasView, text: &str, x: usize, y: usize, length: usize, pad: char, styles: TextStyles) {
+        let used = canvas.write_line(text, x, y, length, styles);
+        canvas.line(pad, x + used, y, length - used, styles)
 }
@@ -0,0 +1,273 @@
+use crate::canvas::{Canvas, Color, Pixel, TextStyles};
+
+use unicode_width::UnicodeWidthChar;
+
+use std::io::{self, Write};
+use std::mem;
+use std::str;
+
+enum State {
+    Ground,
+    Escape,
+    Csi(String),
+}
+
+// A headless terminal that interprets the output of Canvas and Renderer so that
+// tests can inspect exactly what would appear on screen. Text running off the
+// right edge is dropped rather than wrapped.
+pub struct Screen {
+    canvas: Canvas,
+    x: usize,
+    y: usize,
+    styles: TextStyles,
+    state: State,
+    partial: Vec<u8>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen {
+            canvas: Canvas::new(width, height, ' '),
+            x: 0,
+            y: 0,
+            styles: TextStyles::new(),
+            state: State::Ground,
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Pixel> {
+        self.canvas.get(x, y)
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    pub fn row(&self, y: usize) -> String {
+        let mut row = String::new();
+        for p in (0..self.canvas.width()).filter_map(|x| self.canvas.get(x, y)) {
+            p.write_text(&mut row).unwrap();
+        }
+        row
+    }
+
+    pub fn text(&self) -> String {
+        (0..self.canvas.height()).map(|y| self.row(y)).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn feed(&mut self, output: &str) {
+        for ch in output.chars() {
+            self.feed_char(ch)
+        }
+    }
+
+    fn feed_char(&mut self, ch: char) {
+        match self.state {
+            State::Ground => match ch {
+                '\x1B' => self.state = State::Escape,
+                '\n' => {
+                    self.x = 0;
+                    self.y += 1;
+                },
+                '\r' => self.x = 0,
+                '\x08' => self.x = self.x.saturating_sub(1),
+                ch if ch < ' ' => (),
+                ch => match ch.width().unwrap_or(1) {
+                    0 => {
+                        let mut lead = self.x.saturating_sub(1);
+                        while lead > 0 && self.canvas.get(lead, self.y).is_some_and(|p| p.continuation) {
+                            lead -= 1;
+                        }
+                        if let Some(p) = self.canvas.get_mut(lead, self.y) {
+                            p.combining.push(ch);
+                        }
+                    },
+                    width => {
+                        let mut buf = [0; 4];
+                        self.canvas.put(ch.encode_utf8(&mut buf), self.x, self.y, self.styles);
+                        self.x += width.min(2);
+                    },
+                },
+            },
+            State::Escape => {
+                self.state = if ch == '[' {
+                    State::Csi(String::new())
+                } else {
+                    State::Ground
+                };
+            },
+            State::Csi(ref mut params) if !('\x40'..='\x7E').contains(&ch) => params.push(ch),
+            State::Csi(_) => {
+                if let State::Csi(params) = mem::replace(&mut self.state, State::Ground) {
+                    self.csi(&params, ch)
+                }
+            },
+        }
+    }
+
+    fn csi(&mut self, params: &str, last: char) {
+        if params.starts_with('?') {
+            return;
+        }
+        let params: Vec<usize> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let first = params[0];
+        let count = first.max(1);
+        match last {
+            'm' => self.sgr(&params),
+            'H' | 'f' => {
+                self.y = count - 1;
+                self.x = params.get(1).cloned().unwrap_or(1).max(1) - 1;
+            },
+            'A' => self.y = self.y.saturating_sub(count),
+            'B' => self.y += count,
+            'C' => self.x += count,
+            'D' => self.x = self.x.saturating_sub(count),
+            'J' => {
+                let width = self.canvas.width();
+                let start = match first {
+                    0 => self.y * width + self.x,
+                    _ => 0,
+                };
+                self.erase(start, width * self.canvas.height());
+            },
+            'K' => {
+                let width = self.canvas.width();
+                self.erase(self.y * width + self.x.min(width), (self.y + 1) * width);
+            },
+            _ => (),
+        }
+    }
+
+    fn erase(&mut self, start: usize, end: usize) {
+        let width = self.canvas.width();
+        for i in start..end {
+            if let Some(p) = self.canvas.get_mut(i % width, i / width) {
+                *p = Pixel::new(' ', TextStyles::new());
+            }
+        }
+    }
+
+    fn sgr(&mut self, params: &[usize]) {
+        let mut params = params.iter().cloned();
+        while let Some(param) = params.next() {
+            self.styles = match param {
+                0 => TextStyles::new(),
+                1 => self.styles.bold(true),
+                3 => self.styles.italics(true),
+                4 => self.styles.underline(true),
+                7 => self.styles.inverse(true),
+                22 => self.styles.bold(false),
+                23 => self.styles.italics(false),
+                24 => self.styles.underline(false),
+                27 => self.styles.inverse(false),
+                30..=37 => self.styles.fg(ansi_color(param - 30)),
+                38 => self.styles.fg(extended_color(&mut params)),
+                39 => self.styles.fg(Color::Default),
+                40..=47 => self.styles.bg(ansi_color(param - 40)),
+                48 => self.styles.bg(extended_color(&mut params)),
+                49 => self.styles.bg(Color::Default),
+                90..=97 => self.styles.fg(ansi_color(param - 90 + 8)),
+                100..=107 => self.styles.bg(ansi_color(param - 100 + 8)),
+                _ => self.styles,
+            }
+        }
+    }
+}
+
+impl Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial.extend_from_slice(buf);
+        let valid = match str::from_utf8(&self.partial) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let output = String::from_utf8(self.partial.drain(..valid).collect()).unwrap();
+        self.feed(&output);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+fn ansi_color(index: usize) -> Color {
+    const COLORS: [Color; 16] = [
+        Color::Black, Color::Red, Color::Green, Color::Yellow,
+        Color::Blue, Color::Magenta, Color::Cyan, Color::White,
+        Color::BrightBlack, Color::BrightRed, Color::BrightGreen, Color::BrightYellow,
+        Color::BrightBlue, Color::BrightMagenta, Color::BrightCyan, Color::BrightWhite,
+    ];
+    COLORS[index]
+}
+
+fn extended_color<I: Iterator<Item = usize>>(params: &mut I) -> Color {
+    match params.next() {
+        Some(5) => Color::Indexed(params.next().unwrap_or(0) as u8),
+        Some(2) => {
+            let r = params.next().unwrap_or(0) as u8;
+            let g = params.next().unwrap_or(0) as u8;
+            let b = params.next().unwrap_or(0) as u8;
+            Color::Rgb(r, g, b)
+        },
+        _ => Color::Default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Renderer;
+
+    fn sample() -> Canvas {
+        let mut c = Canvas::new(8, 3, '.');
+        c.text("bold", 0, 0, TextStyles::new().bold(true).fg(Color::BrightCyan));
+        c.text("rgb", 4, 1, TextStyles::new().bg(Color::Rgb(10, 20, 30)).underline(true));
+        c.line('=', 2, 2, 4, TextStyles::new().inverse(true).fg(Color::Indexed(200)));
+        c
+    }
+
+    #[test]
+    fn display_output_round_trips() {
+        let c = sample();
+        let mut screen = Screen::new(8, 3);
+        screen.feed(&c.to_string());
+        assert_eq!(screen.canvas(), &c);
+        assert_eq!(screen.text(), "bold....\n....rgb.\n..====..");
+        assert!(screen.get(1, 0).unwrap().styles.is_bold());
+        assert_eq!(screen.get(3, 2).unwrap().styles.fg_color(), Color::Indexed(200));
+    }
+
+    #[test]
+    fn renderer_output_round_trips() {
+        let mut c = sample();
+        let mut r = Renderer::new();
+        let mut screen = Screen::new(8, 3);
+        r.render(&c, &mut screen).unwrap();
+        assert_eq!(screen.canvas(), &c);
+
+        c.text("ük", 1, 1, TextStyles::new().italics(true).fg(Color::Red));
+        c.line(' ', 0, 0, 2, TextStyles::new());
+        r.render(&c, &mut screen).unwrap();
+        assert_eq!(screen.canvas(), &c);
+        assert_eq!(screen.row(1), ".ük.rgb.");
+
+        c.text("漢e\u{301}", 2, 2, TextStyles::new());
+        r.render(&c, &mut screen).unwrap();
+        assert_eq!(screen.canvas(), &c);
+        assert_eq!(screen.row(2), "..漢e\u{301}=..");
+    }
+
+    #[test]
+    fn erasing_works() {
+        let mut screen = Screen::new(4, 2);
+        screen.feed("abcd\nefgh\x1B[1;3H\x1B[K\x1B[2;2H\x1B[41mX");
+        assert_eq!(screen.text(), "ab  \neXgh");
+        assert_eq!(screen.get(1, 1).unwrap().styles.bg_color(), Color::Red);
+        screen.feed("\x1B[2J");
+        assert_eq!(screen.text(), "    \n    ");
+    }
+}